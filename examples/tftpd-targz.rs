@@ -9,8 +9,7 @@ use async_std::stream::StreamExt;
 use async_std::task::block_on;
 use async_tar::{Archive, Entry};
 use async_tftp::packet;
-use async_tftp::server::{Handler, TftpServerBuilder};
-use std::net::SocketAddr;
+use async_tftp::server::{Handler, RequestContext, TftpServerBuilder};
 
 struct TftpdTarGzHandler {
     archive_path: PathBuf,
@@ -36,7 +35,7 @@ impl Handler for TftpdTarGzHandler {
 
     async fn read_req_open(
         &mut self,
-        _client: &SocketAddr,
+        _ctx: &RequestContext,
         path: &std::path::Path,
     ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
         let req_path = strip_path_prefixes(path.into()).to_owned();
@@ -49,7 +48,7 @@ impl Handler for TftpdTarGzHandler {
         while let Some(Ok(entry)) = entries.next().await {
             if entry
                 .path()
-                .map(|p| strip_path_prefixes(&*p) == req_path)
+                .map(|p| strip_path_prefixes(&p) == req_path)
                 .unwrap_or(false)
             {
                 // We manage to find the entry.
@@ -69,7 +68,7 @@ impl Handler for TftpdTarGzHandler {
 
     async fn write_req_open(
         &mut self,
-        _client: &SocketAddr,
+        _ctx: &RequestContext,
         _path: &std::path::Path,
         _size: Option<u64>,
     ) -> Result<Self::Writer, packet::Error> {
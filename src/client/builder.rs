@@ -0,0 +1,92 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use super::{ClientConfig, TftpClient};
+
+/// TFTP client builder.
+pub struct TftpClientBuilder {
+    bind_addr: Option<SocketAddr>,
+    timeout: Duration,
+    block_size: Option<u16>,
+    max_send_retries: u32,
+}
+
+impl Default for TftpClientBuilder {
+    fn default() -> Self {
+        TftpClientBuilder::new()
+    }
+}
+
+impl TftpClientBuilder {
+    /// Create new builder.
+    pub fn new() -> Self {
+        TftpClientBuilder {
+            bind_addr: None,
+            timeout: Duration::from_secs(3),
+            block_size: None,
+            max_send_retries: 100,
+        }
+    }
+
+    /// Set local address of the transfer sockets.
+    ///
+    /// Each transfer binds a new socket on this address. Use port `0` to
+    /// let the OS pick a different port (i.e. transfer ID) for every
+    /// transfer.
+    ///
+    /// **Default:** `0.0.0.0:0` or `[::]:0`, depending on server's address
+    pub fn bind(self, addr: SocketAddr) -> Self {
+        TftpClientBuilder {
+            bind_addr: Some(addr),
+            ..self
+        }
+    }
+
+    /// Set retry timeout.
+    ///
+    /// **Default:** 3 seconds
+    pub fn timeout(self, timeout: Duration) -> Self {
+        TftpClientBuilder {
+            timeout,
+            ..self
+        }
+    }
+
+    /// Request a specific block size (RFC2348).
+    ///
+    /// Server can reply with a smaller block size or ignore the option, in
+    /// which case 512 block size of RFC1350 is used.
+    pub fn block_size(self, size: u16) -> Self {
+        TftpClientBuilder {
+            block_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Set maximum send retries for a packet.
+    ///
+    /// On timeout client will send the last packet again. When retries are
+    /// reached the transfer fails.
+    ///
+    /// Default: 100 retries.
+    pub fn max_send_retries(self, retries: u32) -> Self {
+        TftpClientBuilder {
+            max_send_retries: retries,
+            ..self
+        }
+    }
+
+    /// Build [`TftpClient`].
+    pub fn build(self) -> TftpClient {
+        let config = ClientConfig {
+            bind_addr: self.bind_addr,
+            timeout: self.timeout,
+            block_size: self.block_size,
+            max_send_retries: self.max_send_retries,
+        };
+
+        TftpClient {
+            config,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use futures_lite::{AsyncRead, AsyncWrite};
+use log::trace;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use super::read_req::*;
+use super::write_req::*;
+use crate::error::*;
+
+/// TFTP client.
+///
+/// Client is executor agnostic and it can be used from any runtime. Every
+/// transfer uses its own UDP socket, so a single client can run multiple
+/// transfers concurrently.
+pub struct TftpClient {
+    pub(crate) config: ClientConfig,
+}
+
+#[derive(Clone)]
+pub(crate) struct ClientConfig {
+    pub(crate) bind_addr: Option<SocketAddr>,
+    pub(crate) timeout: Duration,
+    pub(crate) block_size: Option<u16>,
+    pub(crate) max_send_retries: u32,
+}
+
+impl ClientConfig {
+    /// Returns the address that transfer sockets must bind to.
+    pub(crate) fn local_addr(&self, server: SocketAddr) -> SocketAddr {
+        self.bind_addr.unwrap_or_else(|| {
+            let ip = match server {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            };
+            SocketAddr::new(ip, 0)
+        })
+    }
+}
+
+impl TftpClient {
+    /// Download `filename` from `server` (RRQ) and write it to `writer`.
+    ///
+    /// Returns the number of bytes received.
+    pub async fn get<W>(
+        &self,
+        server: SocketAddr,
+        filename: &str,
+        writer: &mut W,
+    ) -> Result<u64>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        trace!("RRQ sending (server: {}, filename: {})", &server, filename);
+
+        let mut read_req =
+            ReadRequest::init(writer, server, filename, self.config.clone())?;
+
+        read_req.handle().await
+    }
+
+    /// Upload data of `reader` to `server` (WRQ) as `filename`.
+    ///
+    /// If `size` is known it is announced to the server with `tsize`
+    /// option (RFC2349).
+    ///
+    /// Returns the number of bytes sent.
+    pub async fn put<R>(
+        &self,
+        server: SocketAddr,
+        filename: &str,
+        reader: &mut R,
+        size: Option<u64>,
+    ) -> Result<u64>
+    where
+        R: AsyncRead + Unpin,
+    {
+        trace!("WRQ sending (server: {}, filename: {})", &server, filename);
+
+        let mut write_req = WriteRequest::init(
+            reader,
+            server,
+            filename,
+            size,
+            self.config.clone(),
+        )?;
+
+        write_req.handle().await
+    }
+}
@@ -0,0 +1,10 @@
+//! Client side implementation.
+
+mod builder;
+#[allow(clippy::module_inception)]
+mod client;
+mod read_req;
+mod write_req;
+
+pub use self::builder::*;
+pub use self::client::*;
@@ -0,0 +1,246 @@
+use async_io::Async;
+use bytes::{Buf, Bytes, BytesMut};
+use futures_lite::{AsyncWrite, AsyncWriteExt};
+use log::trace;
+use std::cmp;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use super::ClientConfig;
+use crate::error::{Error, Result};
+use crate::packet::{
+    self, Mode, Opts, Packet, RwReq, DEFAULT_BLOCK_SIZE, PACKET_DATA_HEADER_LEN,
+};
+use crate::utils::io_timeout;
+
+enum Reply {
+    Data(Bytes),
+    OAck(Opts),
+    Error(packet::Error),
+}
+
+pub(crate) struct ReadRequest<'w, W>
+where
+    W: AsyncWrite + Unpin,
+{
+    server: SocketAddr,
+    // Server replies from a new port (transfer ID), so this is known only
+    // after the first reply.
+    peer: Option<SocketAddr>,
+    socket: Async<UdpSocket>,
+    writer: &'w mut W,
+    // Packet that is retransmitted on timeout. Until server replies this
+    // is the RRQ itself, after that it is the last ACK.
+    last: Bytes,
+    buffer: BytesMut,
+    block_size: usize,
+    opts_requested: bool,
+    timeout: Duration,
+    max_send_retries: u32,
+}
+
+impl<'w, W> ReadRequest<'w, W>
+where
+    W: AsyncWrite + Unpin,
+{
+    pub(crate) fn init(
+        writer: &'w mut W,
+        server: SocketAddr,
+        filename: &str,
+        config: ClientConfig,
+    ) -> Result<ReadRequest<'w, W>> {
+        let opts = Opts {
+            block_size: config.block_size,
+            ..Opts::default()
+        };
+        let opts_requested = opts != Opts::default();
+
+        let req = Packet::Rrq(RwReq {
+            filename: filename.as_bytes().to_vec(),
+            mode: Mode::Octet,
+            opts,
+        });
+
+        // Block size that we requested, until server accepts it or replies
+        // with data of the default block size.
+        let block_size =
+            config.block_size.map(usize::from).unwrap_or(DEFAULT_BLOCK_SIZE);
+
+        let addr = config.local_addr(server);
+        let socket = Async::<UdpSocket>::bind(addr).map_err(Error::Bind)?;
+
+        Ok(ReadRequest {
+            server,
+            peer: None,
+            socket,
+            writer,
+            last: req.to_bytes(),
+            buffer: BytesMut::new(),
+            block_size,
+            opts_requested,
+            timeout: config.timeout,
+            max_send_retries: config.max_send_retries,
+        })
+    }
+
+    pub(crate) async fn handle(&mut self) -> Result<u64> {
+        match self.try_handle().await {
+            Ok(len) => Ok(len),
+            // Errors of the server are never replied.
+            Err(e @ Error::Packet(_)) => Err(e),
+            Err(e) => {
+                trace!("RRQ failed (server: {}, error: {})", &self.server, &e);
+
+                if let Some(peer) = self.peer {
+                    let packet_err = packet::Error::Msg(e.to_string());
+                    let buf = Packet::Error(packet_err).to_bytes();
+                    // Errors are never retransmitted.
+                    // We do not care if `send_to` resulted to an IO error.
+                    let _ = self.socket.send_to(&buf[..], peer).await;
+                }
+
+                Err(e)
+            }
+        }
+    }
+
+    async fn try_handle(&mut self) -> Result<u64> {
+        let mut block_id: u16 = 0;
+        let mut received: u64 = 0;
+
+        loop {
+            // Recv data
+            block_id = block_id.wrapping_add(1);
+            let data = self.recv_data(block_id).await?;
+
+            // Write data to writer
+            self.writer.write_all(&data[..]).await?;
+            received += data.len() as u64;
+
+            // ACK is sent by the next `recv_data`, or explicitly if this
+            // was the last block.
+            self.last = Packet::Ack(block_id).to_bytes();
+
+            if data.len() < self.block_size {
+                break;
+            }
+        }
+
+        // Like the server, make sure data are written before the last ACK.
+        self.writer.flush().await?;
+
+        // Last ACK is never retransmitted.
+        if let Some(peer) = self.peer {
+            self.socket.send_to(&self.last[..], peer).await?;
+        }
+
+        trace!("RRQ served (server: {}, bytes: {})", &self.server, received);
+        Ok(received)
+    }
+
+    async fn recv_data(&mut self, block_id: u16) -> Result<Bytes> {
+        for _ in 0..=self.max_send_retries {
+            let dest = self.peer.unwrap_or(self.server);
+            self.socket.send_to(&self.last[..], dest).await?;
+
+            match self.recv_reply(block_id).await {
+                Ok(Reply::Data(data)) => {
+                    if self.opts_requested {
+                        // Server ignored our options
+                        self.opts_requested = false;
+                        self.block_size = DEFAULT_BLOCK_SIZE;
+                    }
+
+                    return Ok(data);
+                }
+                Ok(Reply::OAck(opts)) => {
+                    trace!("RRQ OACK (server: {}, opts: {:?})", dest, &opts);
+
+                    self.apply_oack(opts)?;
+                    // Next iteration acknowledges the OACK.
+                    self.last = Packet::Ack(0).to_bytes();
+                    continue;
+                }
+                Ok(Reply::Error(e)) => return Err(Error::Packet(e)),
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    trace!(
+                        "RRQ (server: {}, block_id: {}) - Timeout",
+                        &self.server,
+                        block_id
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let peer = self.peer.unwrap_or(self.server);
+        Err(Error::MaxSendRetriesReached(peer, block_id))
+    }
+
+    async fn recv_reply(&mut self, block_id: u16) -> io::Result<Reply> {
+        let socket = &mut self.socket;
+        let server = self.server;
+        let peer = &mut self.peer;
+        let opts_requested = self.opts_requested;
+
+        // Until we know which block size server accepted, we must be able
+        // to receive both the one we requested and the default one.
+        let recv_size = if opts_requested {
+            cmp::max(self.block_size, DEFAULT_BLOCK_SIZE)
+        } else {
+            self.block_size
+        };
+
+        self.buffer.resize(PACKET_DATA_HEADER_LEN + recv_size, 0);
+        let mut buf = self.buffer.split();
+
+        io_timeout(self.timeout, async move {
+            loop {
+                let (len, recved_peer) = socket.recv_from(&mut buf[..]).await?;
+
+                match *peer {
+                    // Ignore packets of other transfer IDs
+                    Some(peer) if peer != recved_peer => continue,
+                    // Server must reply from the same IP address
+                    None if recved_peer.ip() != server.ip() => continue,
+                    _ => {}
+                }
+
+                match Packet::decode(&buf[..len]) {
+                    Ok(Packet::Data(recved_block_id, _))
+                        if recved_block_id == block_id =>
+                    {
+                        peer.get_or_insert(recved_peer);
+                        buf.truncate(len);
+                        buf.advance(PACKET_DATA_HEADER_LEN);
+                        return Ok(Reply::Data(buf.freeze()));
+                    }
+                    Ok(Packet::OAck(opts)) if opts_requested => {
+                        peer.get_or_insert(recved_peer);
+                        return Ok(Reply::OAck(opts));
+                    }
+                    Ok(Packet::Error(e)) => return Ok(Reply::Error(e)),
+                    // Ignore the rest
+                    _ => {}
+                }
+            }
+        })
+        .await
+    }
+
+    fn apply_oack(&mut self, opts: Opts) -> Result<()> {
+        self.opts_requested = false;
+
+        match opts.block_size {
+            Some(bsize) if usize::from(bsize) > self.block_size => {
+                return Err(Error::InvalidPacket);
+            }
+            Some(bsize) => self.block_size = usize::from(bsize),
+            None => self.block_size = DEFAULT_BLOCK_SIZE,
+        }
+
+        Ok(())
+    }
+}
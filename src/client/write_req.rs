@@ -0,0 +1,245 @@
+use async_io::Async;
+use bytes::{Bytes, BytesMut};
+use futures_lite::{AsyncRead, AsyncReadExt};
+use log::trace;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use super::ClientConfig;
+use crate::error::{Error, Result};
+use crate::packet::{
+    self, Mode, Opts, Packet, RwReq, DEFAULT_BLOCK_SIZE, PACKET_DATA_HEADER_LEN,
+};
+use crate::utils::io_timeout;
+
+enum Reply {
+    Ack,
+    OAck(Opts),
+    Error(packet::Error),
+}
+
+pub(crate) struct WriteRequest<'r, R>
+where
+    R: AsyncRead + Unpin,
+{
+    server: SocketAddr,
+    // Server replies from a new port (transfer ID), so this is known only
+    // after the first reply.
+    peer: Option<SocketAddr>,
+    socket: Async<UdpSocket>,
+    reader: &'r mut R,
+    // Packet that is retransmitted on timeout. Until server replies this
+    // is the WRQ itself, after that it is the last Data packet.
+    last: Bytes,
+    buffer: BytesMut,
+    block_size: usize,
+    opts_requested: bool,
+    timeout: Duration,
+    max_send_retries: u32,
+}
+
+impl<'r, R> WriteRequest<'r, R>
+where
+    R: AsyncRead + Unpin,
+{
+    pub(crate) fn init(
+        reader: &'r mut R,
+        server: SocketAddr,
+        filename: &str,
+        size: Option<u64>,
+        config: ClientConfig,
+    ) -> Result<WriteRequest<'r, R>> {
+        let opts = Opts {
+            block_size: config.block_size,
+            transfer_size: size,
+            ..Opts::default()
+        };
+        let opts_requested = opts != Opts::default();
+
+        let req = Packet::Wrq(RwReq {
+            filename: filename.as_bytes().to_vec(),
+            mode: Mode::Octet,
+            opts,
+        });
+
+        // This is replaced with the block size that server accepted.
+        let block_size =
+            config.block_size.map(usize::from).unwrap_or(DEFAULT_BLOCK_SIZE);
+
+        let addr = config.local_addr(server);
+        let socket = Async::<UdpSocket>::bind(addr).map_err(Error::Bind)?;
+
+        Ok(WriteRequest {
+            server,
+            peer: None,
+            socket,
+            reader,
+            last: req.to_bytes(),
+            buffer: BytesMut::new(),
+            block_size,
+            opts_requested,
+            timeout: config.timeout,
+            max_send_retries: config.max_send_retries,
+        })
+    }
+
+    pub(crate) async fn handle(&mut self) -> Result<u64> {
+        match self.try_handle().await {
+            Ok(len) => Ok(len),
+            // Errors of the server are never replied.
+            Err(e @ Error::Packet(_)) => Err(e),
+            Err(e) => {
+                trace!("WRQ failed (server: {}, error: {})", &self.server, &e);
+
+                if let Some(peer) = self.peer {
+                    let packet_err = packet::Error::Msg(e.to_string());
+                    let buf = Packet::Error(packet_err).to_bytes();
+                    // Errors are never retransmitted.
+                    // We do not care if `send_to` resulted to an IO error.
+                    let _ = self.socket.send_to(&buf[..], peer).await;
+                }
+
+                Err(e)
+            }
+        }
+    }
+
+    async fn try_handle(&mut self) -> Result<u64> {
+        let mut block_id: u16 = 0;
+        let mut sent: u64 = 0;
+
+        // Send WRQ and wait for ACK/OACK
+        self.send(block_id).await?;
+
+        loop {
+            block_id = block_id.wrapping_add(1);
+
+            // Encode head of Data packet and read block after it
+            Packet::encode_data_head(block_id, &mut self.buffer);
+            self.buffer.resize(PACKET_DATA_HEADER_LEN + self.block_size, 0);
+
+            let len = self.read_block().await?;
+            let is_last_block = len < self.block_size;
+
+            self.buffer.truncate(PACKET_DATA_HEADER_LEN + len);
+            self.last = self.buffer.split().freeze();
+
+            // Send Data packet
+            self.send(block_id).await?;
+            sent += len as u64;
+
+            if is_last_block {
+                break;
+            }
+        }
+
+        trace!("WRQ served (server: {}, bytes: {})", &self.server, sent);
+        Ok(sent)
+    }
+
+    async fn send(&mut self, block_id: u16) -> Result<()> {
+        // Send packet until we receive an ack
+        for _ in 0..=self.max_send_retries {
+            let dest = self.peer.unwrap_or(self.server);
+            self.socket.send_to(&self.last[..], dest).await?;
+
+            match self.recv_reply(block_id).await {
+                Ok(Reply::Ack) => {
+                    if block_id == 0 && self.opts_requested {
+                        // Server ignored our options
+                        self.opts_requested = false;
+                        self.block_size = DEFAULT_BLOCK_SIZE;
+                    }
+
+                    return Ok(());
+                }
+                Ok(Reply::OAck(opts)) => {
+                    trace!("WRQ OACK (server: {}, opts: {:?})", dest, &opts);
+                    return self.apply_oack(opts);
+                }
+                Ok(Reply::Error(e)) => return Err(Error::Packet(e)),
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    trace!(
+                        "WRQ (server: {}, block_id: {}) - Timeout",
+                        &self.server,
+                        block_id
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let peer = self.peer.unwrap_or(self.server);
+        Err(Error::MaxSendRetriesReached(peer, block_id))
+    }
+
+    async fn recv_reply(&mut self, block_id: u16) -> io::Result<Reply> {
+        let socket = &mut self.socket;
+        let server = self.server;
+        let peer = &mut self.peer;
+        let oack_expected = self.opts_requested && block_id == 0;
+
+        io_timeout(self.timeout, async move {
+            let mut buf = [0u8; 1024];
+
+            loop {
+                let (len, recved_peer) = socket.recv_from(&mut buf[..]).await?;
+
+                match *peer {
+                    // Ignore packets of other transfer IDs
+                    Some(peer) if peer != recved_peer => continue,
+                    // Server must reply from the same IP address
+                    None if recved_peer.ip() != server.ip() => continue,
+                    _ => {}
+                }
+
+                match Packet::decode(&buf[..len]) {
+                    Ok(Packet::Ack(recved_block_id))
+                        if recved_block_id == block_id =>
+                    {
+                        peer.get_or_insert(recved_peer);
+                        return Ok(Reply::Ack);
+                    }
+                    Ok(Packet::OAck(opts)) if oack_expected => {
+                        peer.get_or_insert(recved_peer);
+                        return Ok(Reply::OAck(opts));
+                    }
+                    Ok(Packet::Error(e)) => return Ok(Reply::Error(e)),
+                    // Ignore the rest
+                    _ => {}
+                }
+            }
+        })
+        .await
+    }
+
+    fn apply_oack(&mut self, opts: Opts) -> Result<()> {
+        self.opts_requested = false;
+
+        match opts.block_size {
+            Some(bsize) if usize::from(bsize) > self.block_size => {
+                return Err(Error::InvalidPacket);
+            }
+            Some(bsize) => self.block_size = usize::from(bsize),
+            None => self.block_size = DEFAULT_BLOCK_SIZE,
+        }
+
+        Ok(())
+    }
+
+    async fn read_block(&mut self) -> Result<usize> {
+        let buf = &mut self.buffer[PACKET_DATA_HEADER_LEN..];
+        let mut len = 0;
+
+        while len < buf.len() {
+            match self.reader.read(&mut buf[len..]).await? {
+                0 => break,
+                x => len += x,
+            }
+        }
+
+        Ok(len)
+    }
+}
@@ -5,6 +5,7 @@ pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 /// Error type of this crate.
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum Error {
     #[error("Invalid packet")]
     InvalidPacket,
@@ -21,6 +22,27 @@ pub enum Error {
     #[error("Path '{}' is not a directory", .0.display())]
     NotDir(std::path::PathBuf),
 
+    #[error("Invalid CIDR '{0}'")]
+    InvalidCidr(String),
+
+    #[error("Unknown transfer mode '{0}'")]
+    UnknownMode(String),
+
+    #[error("Unsupported transfer mode '{}'", .0.to_str())]
+    UnsupportedMode(crate::packet::Mode),
+
+    #[error("No free port in transfer port range {}-{}", .0.start(), .0.end())]
+    PortRangeExhausted(std::ops::RangeInclusive<u16>),
+
+    #[error("Too many transfers in progress")]
+    TooManyTransfers,
+
+    #[error("Server is shutting down")]
+    Shutdown,
+
+    #[error("Peer sent error {}: {}", .0.code(), .1)]
+    PeerError(crate::packet::Error, String),
+
     #[error("Max send retries reached (peer: {0},  block id: {1})")]
     MaxSendRetriesReached(std::net::SocketAddr, u16),
 }
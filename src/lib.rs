@@ -1,5 +1,5 @@
 //! Executor agnostic async TFTP implementation, written with [smol]
-//! building blocks. Currently it implements only server side.
+//! building blocks. It implements both server and client side.
 //!
 //! The following RFCs are implemented:
 //!
@@ -7,12 +7,14 @@
 //! * [RFC 2347] - TFTP Option Extension.
 //! * [RFC 2348] - TFTP Blocksize Option.
 //! * [RFC 2349] - TFTP Timeout Interval and Transfer Size Options.
+//! * [RFC 7440] - TFTP Windowsize Option.
 //!
 //! Features:
 //!
 //! * Async implementation.
 //! * Works with any runtime/executor.
 //! * Serve read (RRQ) and write (WRQ) requests.
+//! * Download and upload files with [`TftpClient`].
 //! * Unlimited transfer file size (block number roll-over).
 //! * You can set non-standard reply [`timeout`]. This is useful for faster
 //!   file transfer in unstable environments.
@@ -20,6 +22,8 @@
 //!   client through a VPN.
 //! * You can implement your own [`Handler`] for more advance cases than
 //!   just serving a directory. Check [`tftpd-targz.rs`] for an example.
+//! * With `prometheus` feature, server can serve its statistics to
+//!   Prometheus over HTTP.
 //!
 //! # Example
 //!
@@ -49,19 +53,23 @@
 //! [`timeout`]: server::TftpServerBuilder::timeout
 //! [block size limit]: server::TftpServerBuilder::block_size_limit
 //! [`Handler`]: server::Handler
+//! [`TftpClient`]: client::TftpClient
 //! [`tftpd-targz.rs`]: https://github.com/oblique/async-tftp-rs/blob/master/examples/tftpd-targz.rs
 //!
 //! [RFC 1350]: https://tools.ietf.org/html/rfc1350
 //! [RFC 2347]: https://tools.ietf.org/html/rfc2347
 //! [RFC 2348]: https://tools.ietf.org/html/rfc2348
 //! [RFC 2349]: https://tools.ietf.org/html/rfc2349
+//! [RFC 7440]: https://tools.ietf.org/html/rfc7440
 
+pub mod client;
 pub mod server;
 
 /// Packet definitions that are needed in public API.
 pub mod packet;
 
 mod error;
+mod netascii;
 mod parse;
 mod tests;
 mod utils;
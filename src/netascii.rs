@@ -0,0 +1,209 @@
+//! Netascii translation of RFC1350.
+//!
+//! Netascii uses `CR LF` as line ending and `CR NUL` for a bare `CR`. Data
+//! of handlers are expected to have `LF` line endings.
+use futures_lite::io::BufReader;
+use futures_lite::{ready, AsyncBufRead, AsyncRead, AsyncWrite};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Reader adapter that encodes data of `R` to netascii.
+pub(crate) struct NetasciiReader<R> {
+    inner: BufReader<R>,
+    // Second byte of an encoded pair that did not fit in the previous read.
+    pending: Option<u8>,
+    // Error of `inner` that happened after some data were already encoded.
+    error: Option<io::Error>,
+}
+
+impl<R> NetasciiReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    pub(crate) fn new(reader: R) -> Self {
+        NetasciiReader {
+            inner: BufReader::new(reader),
+            pending: None,
+            error: None,
+        }
+    }
+}
+
+impl<R> AsyncRead for NetasciiReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let mut len = 0;
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        if let Some(byte) = this.pending.take() {
+            buf[0] = byte;
+            len = 1;
+        } else if let Some(e) = this.error.take() {
+            return Poll::Ready(Err(e));
+        }
+
+        while len < buf.len() && this.error.is_none() {
+            let data = match Pin::new(&mut this.inner).poll_fill_buf(cx) {
+                Poll::Ready(Ok(data)) => data,
+                Poll::Ready(Err(e)) if len == 0 => return Poll::Ready(Err(e)),
+                // Return what we have and report the error on next read
+                Poll::Ready(Err(e)) => {
+                    this.error = Some(e);
+                    break;
+                }
+                Poll::Pending if len == 0 => return Poll::Pending,
+                Poll::Pending => break,
+            };
+
+            // EOF
+            if data.is_empty() {
+                break;
+            }
+
+            let mut consumed = 0;
+
+            for &byte in data {
+                if len == buf.len() {
+                    break;
+                }
+
+                let (first, second) = match byte {
+                    b'\n' => (b'\r', Some(b'\n')),
+                    b'\r' => (b'\r', Some(b'\0')),
+                    byte => (byte, None),
+                };
+
+                buf[len] = first;
+                len += 1;
+                consumed += 1;
+
+                if let Some(second) = second {
+                    if len < buf.len() {
+                        buf[len] = second;
+                        len += 1;
+                    } else {
+                        this.pending = Some(second);
+                    }
+                }
+            }
+
+            Pin::new(&mut this.inner).consume(consumed);
+        }
+
+        Poll::Ready(Ok(len))
+    }
+}
+
+/// Writer adapter that decodes netascii data before writing them to `W`.
+pub(crate) struct NetasciiWriter<W> {
+    inner: W,
+    // Decoded data that are not written to `inner` yet.
+    buffer: Vec<u8>,
+    // Last byte was `CR` and we need the next one to decode it.
+    pending_cr: bool,
+}
+
+impl<W> NetasciiWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    pub(crate) fn new(writer: W) -> Self {
+        NetasciiWriter {
+            inner: writer,
+            buffer: Vec::new(),
+            pending_cr: false,
+        }
+    }
+
+    fn decode(&mut self, buf: &[u8]) {
+        for &byte in buf {
+            match (self.pending_cr, byte) {
+                (true, b'\n') => self.buffer.push(b'\n'),
+                (true, b'\0') => self.buffer.push(b'\r'),
+                // Invalid netascii, keep `CR` as is.
+                (true, b'\r') => self.buffer.push(b'\r'),
+                (true, byte) => self.buffer.extend_from_slice(&[b'\r', byte]),
+                (false, b'\r') => {}
+                (false, byte) => self.buffer.push(byte),
+            }
+
+            self.pending_cr = byte == b'\r';
+        }
+    }
+
+    fn poll_write_buffer(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        while !self.buffer.is_empty() {
+            let inner = Pin::new(&mut self.inner);
+
+            match ready!(inner.poll_write(cx, &self.buffer))? {
+                0 => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                len => self.buffer.drain(..len),
+            };
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W> AsyncWrite for NetasciiWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        // Make room for the new data
+        ready!(this.poll_write_buffer(cx))?;
+
+        this.decode(buf);
+
+        // Try to write them right away, otherwise they will be written by
+        // the next call.
+        if let Poll::Ready(Err(e)) = this.poll_write_buffer(cx) {
+            return Poll::Ready(Err(e));
+        }
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        // Invalid netascii, keep the trailing `CR` as is.
+        if this.pending_cr {
+            this.pending_cr = false;
+            this.buffer.push(b'\r');
+        }
+
+        ready!(this.poll_write_buffer(cx))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
@@ -1,14 +1,16 @@
-///! Packet definitions.
+//! Packet definitions.
 use bytes::{BufMut, Bytes, BytesMut};
 use num_derive::FromPrimitive;
 use std::convert::From;
 use std::io;
 use std::str;
+use std::time::Duration;
 
 use crate::error::Result;
 use crate::parse::*;
 
 pub(crate) const PACKET_DATA_HEADER_LEN: usize = 4;
+pub(crate) const DEFAULT_BLOCK_SIZE: usize = 512;
 
 #[derive(Debug, Clone, Copy, PartialEq, FromPrimitive)]
 #[repr(u16)]
@@ -23,6 +25,7 @@ pub(crate) enum PacketType {
 
 /// TFTP protocol error. Should not be confused with `async_tftp::Error`.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum Error {
     Msg(String),
     UnknownError,
@@ -33,6 +36,8 @@ pub enum Error {
     UnknownTransferId,
     FileAlreadyExists,
     NoSuchUser,
+    /// Transfer is terminated because of its options (RFC2347).
+    OptionNegotiation,
 }
 
 #[derive(Debug)]
@@ -45,29 +50,44 @@ pub(crate) enum Packet<'a> {
     OAck(Opts),
 }
 
-#[derive(Debug, PartialEq)]
-pub(crate) enum Mode {
+/// Transfer mode of a request.
+///
+/// Data of `Netascii` transfers are translated by the server, so handlers
+/// always read and write them with `LF` line endings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
     Netascii,
     Octet,
+    /// Obsolete mode of RFC1350. Requests of this mode are rejected.
     Mail,
 }
 
 #[derive(Debug, PartialEq)]
 pub(crate) struct RwReq {
-    pub filename: String,
+    pub filename: Vec<u8>,
     pub mode: Mode,
     pub opts: Opts,
 }
 
+/// Options of a request (RFC2347).
 #[derive(Debug, Clone, Default, PartialEq)]
-pub(crate) struct Opts {
+#[non_exhaustive]
+pub struct Opts {
+    /// `blksize` option (RFC2348).
     pub block_size: Option<u16>,
+    /// `timeout` option in seconds (RFC2349).
     pub timeout: Option<u8>,
+    /// `utimeout` option in microseconds. This is an extension of
+    /// `timeout` that is supported by tftp-hpa and other implementations.
+    pub timeout_us: Option<u32>,
+    /// `tsize` option (RFC2349).
     pub transfer_size: Option<u64>,
+    /// `windowsize` option (RFC7440).
+    pub window_size: Option<u16>,
 }
 
 impl<'a> Packet<'a> {
-    pub(crate) fn decode(data: &[u8]) -> Result<Packet> {
+    pub(crate) fn decode(data: &[u8]) -> Result<Packet<'_>> {
         parse_packet(data)
     }
 
@@ -75,7 +95,7 @@ impl<'a> Packet<'a> {
         match self {
             Packet::Rrq(req) => {
                 buf.put_u16(PacketType::Rrq as u16);
-                buf.put_slice(req.filename.as_bytes());
+                buf.put_slice(&req.filename);
                 buf.put_u8(0);
                 buf.put_slice(req.mode.to_str().as_bytes());
                 buf.put_u8(0);
@@ -83,7 +103,7 @@ impl<'a> Packet<'a> {
             }
             Packet::Wrq(req) => {
                 buf.put_u16(PacketType::Wrq as u16);
-                buf.put_slice(req.filename.as_bytes());
+                buf.put_slice(&req.filename);
                 buf.put_u8(0);
                 buf.put_slice(req.mode.to_str().as_bytes());
                 buf.put_u8(0);
@@ -124,6 +144,15 @@ impl<'a> Packet<'a> {
 }
 
 impl Opts {
+    /// Timeout of `utimeout` option, or else of `timeout` option.
+    pub(crate) fn timeout_duration(&self) -> Option<Duration> {
+        match (self.timeout_us, self.timeout) {
+            (Some(us), _) => Some(Duration::from_micros(u64::from(us))),
+            (None, Some(secs)) => Some(Duration::from_secs(u64::from(secs))),
+            (None, None) => None,
+        }
+    }
+
     fn encode(&self, buf: &mut BytesMut) {
         if let Some(block_size) = self.block_size {
             buf.put_slice(&b"blksize\0"[..]);
@@ -137,16 +166,28 @@ impl Opts {
             buf.put_u8(0);
         }
 
+        if let Some(timeout_us) = self.timeout_us {
+            buf.put_slice(&b"utimeout\0"[..]);
+            buf.put_slice(timeout_us.to_string().as_bytes());
+            buf.put_u8(0);
+        }
+
         if let Some(transfer_size) = self.transfer_size {
             buf.put_slice(&b"tsize\0"[..]);
             buf.put_slice(transfer_size.to_string().as_bytes());
             buf.put_u8(0);
         }
+
+        if let Some(window_size) = self.window_size {
+            buf.put_slice(&b"windowsize\0"[..]);
+            buf.put_slice(window_size.to_string().as_bytes());
+            buf.put_u8(0);
+        }
     }
 }
 
 impl Mode {
-    pub(crate) fn to_str(&self) -> &'static str {
+    pub(crate) fn to_str(self) -> &'static str {
         match self {
             Mode::Netascii => "netascii",
             Mode::Octet => "octet",
@@ -166,6 +207,7 @@ impl Error {
             5 => Error::UnknownTransferId,
             6 => Error::FileAlreadyExists,
             7 => Error::NoSuchUser,
+            8 => Error::OptionNegotiation,
             0 | _ => match msg {
                 Some(msg) => Error::Msg(msg.to_string()),
                 None => Error::UnknownError,
@@ -173,7 +215,8 @@ impl Error {
         }
     }
 
-    pub(crate) fn code(&self) -> u16 {
+    /// Error code of RFC1350.
+    pub fn code(&self) -> u16 {
         match self {
             Error::Msg(..) => 0,
             Error::UnknownError => 0,
@@ -184,6 +227,7 @@ impl Error {
             Error::UnknownTransferId => 5,
             Error::FileAlreadyExists => 6,
             Error::NoSuchUser => 7,
+            Error::OptionNegotiation => 8,
         }
     }
 
@@ -198,6 +242,7 @@ impl Error {
             Error::UnknownTransferId => "Unknown transfer ID",
             Error::FileAlreadyExists => "File already exists",
             Error::NoSuchUser => "No such user",
+            Error::OptionNegotiation => "Option negotiation failed",
         }
     }
 }
@@ -210,6 +255,12 @@ impl From<Error> for Packet<'_> {
 
 impl From<io::Error> for Error {
     fn from(io_err: io::Error) -> Self {
+        Error::from(&io_err)
+    }
+}
+
+impl From<&io::Error> for Error {
+    fn from(io_err: &io::Error) -> Self {
         match io_err.kind() {
             io::ErrorKind::NotFound => Error::FileNotFound,
             io::ErrorKind::PermissionDenied => Error::PermissionDenied,
@@ -225,10 +276,24 @@ impl From<io::Error> for Error {
 
 impl From<crate::Error> for Error {
     fn from(err: crate::Error) -> Self {
+        Error::from(&err)
+    }
+}
+
+impl From<&crate::Error> for Error {
+    fn from(err: &crate::Error) -> Self {
         match err {
-            crate::Error::Packet(e) => e,
+            crate::Error::Packet(e) => e.clone(),
+            crate::Error::PeerError(e, _) => e.clone(),
             crate::Error::Io(e) => e.into(),
             crate::Error::InvalidPacket => Error::IllegalOperation,
+            crate::Error::UnknownMode(_) => Error::IllegalOperation,
+            crate::Error::UnsupportedMode(_) => Error::IllegalOperation,
+            e @ crate::Error::PortRangeExhausted(_) => {
+                Error::Msg(e.to_string())
+            }
+            e @ crate::Error::TooManyTransfers => Error::Msg(e.to_string()),
+            e @ crate::Error::Shutdown => Error::Msg(e.to_string()),
             crate::Error::MaxSendRetriesReached(..) => {
                 Error::Msg("Max retries reached".to_string())
             }
@@ -236,3 +301,24 @@ impl From<crate::Error> for Error {
         }
     }
 }
+
+/// Encode the ERROR packet that replies to `err`.
+///
+/// Unlike `Packet::Error(err.into())`, this keeps the description of mode
+/// errors, which are replied with the code of `IllegalOperation`.
+pub(crate) fn encode_error_reply(err: &crate::Error) -> Bytes {
+    let error = Error::from(err);
+    let msg = match err {
+        crate::Error::UnknownMode(_) | crate::Error::UnsupportedMode(_) => {
+            err.to_string()
+        }
+        _ => error.msg().to_owned(),
+    };
+
+    let mut buf = BytesMut::new();
+    buf.put_u16(PacketType::Error as u16);
+    buf.put_u16(error.code());
+    buf.put_slice(msg.as_bytes());
+    buf.put_u8(0);
+    buf.freeze()
+}
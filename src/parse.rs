@@ -12,14 +12,16 @@ use crate::error::Result;
 use crate::packet::{self, *};
 
 #[derive(Debug)]
-enum Opt<'a> {
+enum Opt {
     BlkSize(u16),
     Timeout(u8),
+    TimeoutUs(u32),
     Tsize(u64),
-    Invalid(&'a str, &'a str),
+    WindowSize(u16),
+    Invalid,
 }
 
-pub(crate) fn parse_packet(input: &[u8]) -> Result<Packet> {
+pub(crate) fn parse_packet(input: &[u8]) -> Result<Packet<'_>> {
     let (rest, packet) = match parse_packet_type(input)? {
         (data, PacketType::Rrq) => parse_rrq(data)?,
         (data, PacketType::Wrq) => parse_wrq(data)?,
@@ -36,23 +38,28 @@ pub(crate) fn parse_packet(input: &[u8]) -> Result<Packet> {
     }
 }
 
+fn nul_bytes(input: &[u8]) -> IResult<&[u8], &[u8]> {
+    map(tuple((take_till(|c| c == b'\0'), tag(b"\0"))), |(s, _)| s)(input)
+}
+
 fn nul_str(input: &[u8]) -> IResult<&[u8], &str> {
-    map_res(
-        tuple((take_till(|c| c == b'\0'), tag(b"\0"))),
-        |(s, _): (&[u8], _)| str::from_utf8(s),
-    )(input)
+    map_res(nul_bytes, str::from_utf8)(input)
 }
 
 fn parse_packet_type(input: &[u8]) -> IResult<&[u8], PacketType> {
     map_opt(be_u16, PacketType::from_u16)(input)
 }
 
-fn parse_mode(input: &[u8]) -> IResult<&[u8], Mode> {
-    alt((
-        map(tag_no_case(b"netascii\0"), |_| Mode::Netascii),
-        map(tag_no_case(b"octet\0"), |_| Mode::Octet),
-        map(tag_no_case(b"mail\0"), |_| Mode::Mail),
-    ))(input)
+fn parse_mode(mode: &str) -> Option<Mode> {
+    if mode.eq_ignore_ascii_case("netascii") {
+        Some(Mode::Netascii)
+    } else if mode.eq_ignore_ascii_case("octet") {
+        Some(Mode::Octet)
+    } else if mode.eq_ignore_ascii_case("mail") {
+        Some(Mode::Mail)
+    } else {
+        None
+    }
 }
 
 fn parse_opt_blksize(input: &[u8]) -> IResult<&[u8], Opt> {
@@ -70,18 +77,41 @@ fn parse_opt_timeout(input: &[u8]) -> IResult<&[u8], Opt> {
     })(input)
 }
 
+fn parse_opt_utimeout(input: &[u8]) -> IResult<&[u8], Opt> {
+    map_opt(
+        tuple((tag_no_case(b"utimeout\0"), nul_str)),
+        |(_, n): (_, &str)| {
+            u32::from_str(n)
+                .ok()
+                .filter(|n| *n >= 10_000 && *n <= 255_000_000)
+                .map(Opt::TimeoutUs)
+        },
+    )(input)
+}
+
 fn parse_opt_tsize(input: &[u8]) -> IResult<&[u8], Opt> {
     map_opt(tuple((tag_no_case(b"tsize\0"), nul_str)), |(_, n): (_, &str)| {
         u64::from_str(n).ok().map(Opt::Tsize)
     })(input)
 }
 
+fn parse_opt_windowsize(input: &[u8]) -> IResult<&[u8], Opt> {
+    map_opt(
+        tuple((tag_no_case(b"windowsize\0"), nul_str)),
+        |(_, n): (_, &str)| {
+            u16::from_str(n).ok().filter(|n| *n >= 1).map(Opt::WindowSize)
+        },
+    )(input)
+}
+
 pub(crate) fn parse_opts(input: &[u8]) -> IResult<&[u8], Opts> {
     many0(alt((
         parse_opt_blksize,
         parse_opt_timeout,
+        parse_opt_utimeout,
         parse_opt_tsize,
-        map(tuple((nul_str, nul_str)), |(k, v)| Opt::Invalid(k, v)),
+        parse_opt_windowsize,
+        map(tuple((nul_str, nul_str)), |_| Opt::Invalid),
     )))(input)
     .map(|(i, opt_vec)| (i, to_opts(opt_vec)))
 }
@@ -101,61 +131,77 @@ fn to_opts(opt_vec: Vec<Opt>) -> Opts {
                     opts.timeout.replace(timeout);
                 }
             }
+            Opt::TimeoutUs(timeout) => {
+                if opts.timeout_us.is_none() {
+                    opts.timeout_us.replace(timeout);
+                }
+            }
             Opt::Tsize(size) => {
                 if opts.transfer_size.is_none() {
                     opts.transfer_size.replace(size);
                 }
             }
-            Opt::Invalid(..) => {}
+            Opt::WindowSize(size) => {
+                if opts.window_size.is_none() {
+                    opts.window_size.replace(size);
+                }
+            }
+            Opt::Invalid => {}
         }
     }
 
     opts
 }
 
-fn parse_rrq(input: &[u8]) -> IResult<&[u8], Packet> {
-    let (input, (filename, mode, opts)) =
-        tuple((nul_str, parse_mode, parse_opts))(input)?;
+fn parse_rwreq(input: &[u8]) -> Result<(&[u8], RwReq)> {
+    // Filenames are not required to be UTF-8
+    let (input, (filename, mode)) = tuple((nul_bytes, nul_str))(input)?;
+
+    let mode = parse_mode(mode)
+        .ok_or_else(|| crate::Error::UnknownMode(mode.to_owned()))?;
+
+    let (input, opts) = parse_opts(input)?;
 
     Ok((
         input,
-        Packet::Rrq(RwReq {
-            filename: filename.to_owned(),
+        RwReq {
+            filename: filename.to_vec(),
             mode,
             opts,
-        }),
+        },
     ))
 }
 
-fn parse_wrq(input: &[u8]) -> IResult<&[u8], Packet> {
-    let (input, (filename, mode, opts)) =
-        tuple((nul_str, parse_mode, parse_opts))(input)?;
+fn parse_rrq(input: &[u8]) -> Result<(&[u8], Packet<'_>)> {
+    parse_rwreq(input).map(|(i, req)| (i, Packet::Rrq(req)))
+}
 
-    Ok((
-        input,
-        Packet::Wrq(RwReq {
-            filename: filename.to_owned(),
-            mode,
-            opts,
-        }),
-    ))
+fn parse_wrq(input: &[u8]) -> Result<(&[u8], Packet<'_>)> {
+    parse_rwreq(input).map(|(i, req)| (i, Packet::Wrq(req)))
 }
 
-fn parse_data(input: &[u8]) -> IResult<&[u8], Packet> {
+fn parse_data(input: &[u8]) -> IResult<&[u8], Packet<'_>> {
     tuple((be_u16, rest))(input)
         .map(|(i, (block_nr, data))| (i, Packet::Data(block_nr, data)))
 }
 
-fn parse_ack(input: &[u8]) -> IResult<&[u8], Packet> {
+fn parse_ack(input: &[u8]) -> IResult<&[u8], Packet<'_>> {
     be_u16(input).map(|(i, block_nr)| (i, Packet::Ack(block_nr)))
 }
 
-fn parse_error(input: &[u8]) -> IResult<&[u8], Packet> {
+fn parse_error(input: &[u8]) -> IResult<&[u8], Packet<'_>> {
     tuple((be_u16, nul_str))(input).map(|(i, (code, msg))| {
         (i, packet::Error::from_code(code, Some(msg)).into())
     })
 }
 
-fn parse_oack(input: &[u8]) -> IResult<&[u8], Packet> {
+/// Parse the message of an ERROR packet, which is lost when the error code
+/// is known.
+pub(crate) fn parse_error_msg(input: &[u8]) -> Option<&str> {
+    let (_, (_, _, msg)) = tuple((be_u16, be_u16, nul_str))(input).ok()?;
+    Some(msg)
+}
+
+fn parse_oack(input: &[u8]) -> IResult<&[u8], Packet<'_>> {
     parse_opts(input).map(|(i, opts)| (i, Packet::OAck(opts)))
 }
@@ -0,0 +1,141 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+
+/// Range of IP addresses in CIDR notation, such as `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpCidr {
+    /// Create a range of the addresses that have the first `prefix_len`
+    /// bits of `addr`.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self> {
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+
+        if prefix_len > max_len {
+            return Err(Error::InvalidCidr(format!("{}/{}", addr, prefix_len)));
+        }
+
+        Ok(IpCidr {
+            addr,
+            prefix_len,
+        })
+    }
+
+    /// Returns `true` if `ip` is in the range.
+    ///
+    /// IPv4-mapped IPv6 addresses are matched as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, unmap(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = Error;
+
+    /// Parse `addr/prefix_len`. An address without prefix length is a range
+    /// of only this address.
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidCidr(s.to_string());
+
+        let (addr, prefix_len) = match s.find('/') {
+            Some(i) => {
+                let addr = s[..i].parse().map_err(|_| invalid())?;
+                let len = s[i + 1..].parse().map_err(|_| invalid())?;
+                (addr, len)
+            }
+            None => {
+                let addr: IpAddr = s.parse().map_err(|_| invalid())?;
+                let len = if addr.is_ipv4() {
+                    32
+                } else {
+                    128
+                };
+                (addr, len)
+            }
+        };
+
+        IpCidr::new(addr, prefix_len).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// Access control list of client IP addresses.
+///
+/// Client is allowed if it is not in any denied range, and it is in an
+/// allowed range or no allowed ranges are set.
+#[derive(Debug, Clone, Default)]
+pub struct Acl {
+    allow: Vec<IpCidr>,
+    deny: Vec<IpCidr>,
+}
+
+impl Acl {
+    /// Create an ACL that allows all clients.
+    pub fn new() -> Self {
+        Acl::default()
+    }
+
+    /// Allow clients of `range`.
+    pub fn allow(mut self, range: IpCidr) -> Self {
+        self.allow.push(range);
+        self
+    }
+
+    /// Deny clients of `range`. This takes precedence over allowed ranges.
+    pub fn deny(mut self, range: IpCidr) -> Self {
+        self.deny.push(range);
+        self
+    }
+
+    /// Returns `true` if client `ip` is allowed.
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|range| range.contains(ip)) {
+            return false;
+        }
+
+        self.allow.is_empty()
+            || self.allow.iter().any(|range| range.contains(ip))
+    }
+}
+
+/// Convert IPv4-mapped IPv6 address to IPv4.
+fn unmap(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) => match ip.segments() {
+            [0, 0, 0, 0, 0, 0xffff, high, low] => {
+                let ip = (u32::from(high) << 16) | u32::from(low);
+                IpAddr::V4(Ipv4Addr::from(ip))
+            }
+            _ => IpAddr::V6(ip),
+        },
+        ip => ip,
+    }
+}
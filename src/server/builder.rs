@@ -1,26 +1,60 @@
 use async_executor::Executor;
 use async_io::Async;
 use async_lock::Mutex;
-use std::collections::HashSet;
+use log::trace;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::cmp;
+use std::collections::HashMap;
+use std::io;
+#[cfg(feature = "prometheus")]
+use std::net::TcpListener;
 use std::net::{SocketAddr, UdpSocket};
+use std::ops::RangeInclusive;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
+use super::acl::Acl;
 use super::handlers::{DirHandler, DirHandlerMode};
-use super::{Handler, ServerConfig, TftpServer};
+use super::limits::TransferLimits;
+use super::observer::Observer;
+use super::pktinfo;
+use super::socket::PortRange;
+use super::throttle::Throttle;
+use super::{
+    Handler, HandlerRef, Listener, ServerConfig, SharedHandler, TftpServer,
+};
 use crate::error::{Error, Result};
 
 /// TFTP server builder.
 pub struct TftpServerBuilder<H: Handler> {
-    handle: H,
-    addr: SocketAddr,
-    socket: Option<Async<UdpSocket>>,
+    handler: HandlerRef<H>,
+    addrs: Vec<SocketAddr>,
+    sockets: Vec<Async<UdpSocket>>,
     timeout: Duration,
+    adaptive_timeout: Option<(Duration, Duration)>,
     block_size_limit: Option<u16>,
+    min_block_size: Option<u16>,
+    window_size_limit: u16,
     max_send_retries: u32,
     ignore_client_timeout: bool,
     ignore_client_block_size: bool,
+    single_port: bool,
+    port_range: Option<RangeInclusive<u16>>,
+    max_transfers: Option<usize>,
+    max_transfers_per_ip: Option<usize>,
+    transfer_queue_timeout: Duration,
+    rate_limit_per_transfer: Option<u64>,
+    rate_limit_per_ip: Option<u64>,
+    rate_limit_total: Option<u64>,
+    rrq_acl: Acl,
+    wrq_acl: Acl,
+    drop_denied: bool,
+    unknown_tid_reply_limit: Option<u32>,
+    max_write_size: Option<u64>,
+    observer: Option<Arc<dyn Observer>>,
+    #[cfg(feature = "prometheus")]
+    metrics_addr: Option<SocketAddr>,
 }
 
 impl TftpServerBuilder<DirHandler> {
@@ -54,18 +88,52 @@ impl TftpServerBuilder<DirHandler> {
     }
 }
 
-impl<H: Handler> TftpServerBuilder<H> {
+impl<S: SharedHandler + 'static> TftpServerBuilder<Arc<S>> {
+    /// Create new builder with custom [`SharedHandler`].
+    ///
+    /// Unlike [`with_handler`], requests are opened concurrently.
+    ///
+    /// [`with_handler`]: Self::with_handler
+    pub fn with_shared_handler(handler: S) -> Self {
+        TftpServerBuilder::new(Arc::new(handler))
+    }
+}
+
+impl<H: Handler + 'static> TftpServerBuilder<H> {
     /// Create new builder with custom [`Handler`].
     pub fn with_handler(handler: H) -> Self {
+        TftpServerBuilder::new(Arc::new(Mutex::new(handler)))
+    }
+
+    fn new(handler: HandlerRef<H>) -> Self {
         TftpServerBuilder {
-            handle: handler,
-            addr: "0.0.0.0:69".parse().unwrap(),
-            socket: None,
+            handler,
+            addrs: vec!["0.0.0.0:69".parse().unwrap()],
+            sockets: Vec::new(),
             timeout: Duration::from_secs(3),
+            adaptive_timeout: None,
             block_size_limit: None,
+            min_block_size: None,
+            window_size_limit: 64,
             max_send_retries: 100,
             ignore_client_timeout: false,
             ignore_client_block_size: false,
+            single_port: false,
+            port_range: None,
+            max_transfers: None,
+            max_transfers_per_ip: None,
+            transfer_queue_timeout: Duration::from_secs(0),
+            rate_limit_per_transfer: None,
+            rate_limit_per_ip: None,
+            rate_limit_total: None,
+            rrq_acl: Acl::new(),
+            wrq_acl: Acl::new(),
+            drop_denied: false,
+            unknown_tid_reply_limit: None,
+            max_write_size: None,
+            observer: None,
+            #[cfg(feature = "prometheus")]
+            metrics_addr: None,
         }
     }
 
@@ -75,16 +143,45 @@ impl<H: Handler> TftpServerBuilder<H> {
     ///
     /// **Default:** `0.0.0.0:69`
     pub fn bind(self, addr: SocketAddr) -> Self {
+        self.bind_addrs(vec![addr])
+    }
+
+    /// Set multiple listening addresses.
+    ///
+    /// Server listens on all of them, for example on IPv4 and IPv6
+    /// addresses at the same time. Transfers use the local address that
+    /// their request arrived on.
+    ///
+    /// If any of them is an IPv4 address, IPv6 sockets are bound with
+    /// `IPV6_V6ONLY`, so `0.0.0.0` and `[::]` can share a port. Otherwise
+    /// the system default applies, which on Linux makes `[::]` alone accept
+    /// IPv4 too.
+    ///
+    /// This is ignored if underling sockets are set.
+    pub fn bind_addrs<I>(self, addrs: I) -> Self
+    where
+        I: IntoIterator<Item = SocketAddr>,
+    {
         TftpServerBuilder {
-            addr,
+            addrs: addrs.into_iter().collect(),
             ..self
         }
     }
 
     /// Set underling UDP socket.
     pub fn socket(self, socket: Async<UdpSocket>) -> Self {
+        self.sockets(vec![socket])
+    }
+
+    /// Set multiple underling UDP sockets.
+    ///
+    /// See [`bind_addrs`](Self::bind_addrs).
+    pub fn sockets<I>(self, sockets: I) -> Self
+    where
+        I: IntoIterator<Item = Async<UdpSocket>>,
+    {
         TftpServerBuilder {
-            socket: Some(socket),
+            sockets: sockets.into_iter().collect(),
             ..self
         }
     }
@@ -92,16 +189,13 @@ impl<H: Handler> TftpServerBuilder<H> {
     /// Set underling UDP socket.
     pub fn std_socket(self, socket: UdpSocket) -> Result<Self> {
         let socket = Async::new(socket)?;
-
-        Ok(TftpServerBuilder {
-            socket: Some(socket),
-            ..self
-        })
+        Ok(self.socket(socket))
     }
 
     /// Set retry timeout.
     ///
-    /// Client can override this (RFC2349). If you want to enforce it you must
+    /// Client can override this with `timeout` option (RFC2349), or with
+    /// `utimeout` option in microseconds. If you want to enforce it you must
     /// combine it [`ignore_client_timeout`](Self::ignore_client_timeout).
     ///
     /// This crate allows you to set non-standard timeouts (i.e. timeouts that are less
@@ -116,6 +210,25 @@ impl<H: Handler> TftpServerBuilder<H> {
         }
     }
 
+    /// Adapt the retry timeout of read requests to the round-trip time of
+    /// each client, between `min` and `max`.
+    ///
+    /// Timeout starts from [`timeout`](Self::timeout) and is computed like
+    /// the one of TCP (RFC6298): the smoothed round-trip time of the
+    /// acknowledgments plus four times their variance. It doubles on every
+    /// loss. Transfers that negotiated the `timeout` or `utimeout` option of
+    /// the client use the negotiated value instead.
+    ///
+    /// If `min` is greater than `max`, they are swapped.
+    ///
+    /// **Default:** Disabled
+    pub fn adaptive_timeout(self, min: Duration, max: Duration) -> Self {
+        TftpServerBuilder {
+            adaptive_timeout: Some((cmp::min(min, max), cmp::max(min, max))),
+            ..self
+        }
+    }
+
     /// Set maximum block size.
     ///
     /// Client can request a specific block size (RFC2348). Use this option if you
@@ -132,6 +245,36 @@ impl<H: Handler> TftpServerBuilder<H> {
         }
     }
 
+    /// Set minimum block size that clients can request.
+    ///
+    /// Requests with a smaller `blksize` option (RFC2348) are rejected with
+    /// [`OptionNegotiation`](crate::packet::Error::OptionNegotiation) error.
+    /// Requests without the option use the default block size of 512 bytes.
+    ///
+    /// **Default:** Any block size
+    pub fn min_block_size(self, size: u16) -> Self {
+        TftpServerBuilder {
+            min_block_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Set maximum window size.
+    ///
+    /// Client can request to send multiple blocks before waiting for an
+    /// acknowledgment (RFC7440). This speeds up transfers over high-latency
+    /// links, but server needs to keep the whole window in memory for
+    /// retransmissions. Use this option to set a limit, or set it to `1`
+    /// to disable windowed transfers.
+    ///
+    /// **Default:** 64 blocks
+    pub fn window_size_limit(self, size: u16) -> Self {
+        TftpServerBuilder {
+            window_size_limit: cmp::max(size, 1),
+            ..self
+        }
+    }
+
     /// Set maximum send retries for a data block.
     ///
     /// On timeout server will try to send the data block again. When retries are
@@ -168,29 +311,321 @@ impl<H: Handler> TftpServerBuilder<H> {
         }
     }
 
+    /// Serve all transfers from the listening port.
+    ///
+    /// By default every transfer gets a new port (RFC1350), which does not
+    /// work through most NATs and firewalls that allow only the listening
+    /// port. With this the listening socket is shared by all transfers and
+    /// packets are delivered to them based on their peer address.
+    pub fn single_port(self) -> Self {
+        TftpServerBuilder {
+            single_port: true,
+            ..self
+        }
+    }
+
+    /// Bind transfer sockets only to ports of `ports`.
+    ///
+    /// Server cycles through the range and skips ports that are in use.
+    /// Errors to rejected requests are sent from these ports too. Requests
+    /// fail with an error from the listening port if no port is free. This
+    /// has no effect in [`single_port`](Self::single_port) mode.
+    ///
+    /// **Default:** Any port that OS picks.
+    pub fn port_range(self, ports: RangeInclusive<u16>) -> Self {
+        TftpServerBuilder {
+            port_range: Some(ports),
+            ..self
+        }
+    }
+
+    /// Set maximum number of transfers that run at the same time.
+    ///
+    /// Requests above the limit wait for a
+    /// [`transfer_queue_timeout`](Self::transfer_queue_timeout) and then
+    /// they are rejected with an error.
+    ///
+    /// **Default:** Unlimited
+    pub fn max_transfers(self, max: usize) -> Self {
+        TftpServerBuilder {
+            max_transfers: Some(cmp::max(max, 1)),
+            ..self
+        }
+    }
+
+    /// Set maximum number of transfers that a client IP runs at the same
+    /// time.
+    ///
+    /// Requests above the limit are handled like the ones of
+    /// [`max_transfers`](Self::max_transfers).
+    ///
+    /// **Default:** Unlimited
+    pub fn max_transfers_per_ip(self, max: usize) -> Self {
+        TftpServerBuilder {
+            max_transfers_per_ip: Some(cmp::max(max, 1)),
+            ..self
+        }
+    }
+
+    /// Set how long a request waits for a transfer limit before it gets
+    /// rejected.
+    ///
+    /// **Default:** 0 seconds, requests above the limits are rejected
+    /// immediately.
+    pub fn transfer_queue_timeout(self, timeout: Duration) -> Self {
+        TftpServerBuilder {
+            transfer_queue_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Limit bandwidth of each transfer, in bytes per second.
+    ///
+    /// Rate limits count the data bytes of the blocks, without packet
+    /// headers, that server sends for read requests and receives for write
+    /// requests. Each limit allows a burst of one second.
+    ///
+    /// **Default:** Unlimited
+    pub fn rate_limit_per_transfer(self, bytes_per_sec: u64) -> Self {
+        TftpServerBuilder {
+            rate_limit_per_transfer: Some(cmp::max(bytes_per_sec, 1)),
+            ..self
+        }
+    }
+
+    /// Limit bandwidth of all transfers of a client IP, in bytes per second.
+    ///
+    /// **Default:** Unlimited
+    pub fn rate_limit_per_ip(self, bytes_per_sec: u64) -> Self {
+        TftpServerBuilder {
+            rate_limit_per_ip: Some(cmp::max(bytes_per_sec, 1)),
+            ..self
+        }
+    }
+
+    /// Limit bandwidth of all transfers of the server, in bytes per second.
+    ///
+    /// **Default:** Unlimited
+    pub fn rate_limit_total(self, bytes_per_sec: u64) -> Self {
+        TftpServerBuilder {
+            rate_limit_total: Some(cmp::max(bytes_per_sec, 1)),
+            ..self
+        }
+    }
+
+    /// Set which clients can send read requests.
+    ///
+    /// Requests of denied clients are rejected with a `PermissionDenied`
+    /// error before they reach the handler.
+    ///
+    /// **Default:** All clients are allowed.
+    pub fn rrq_acl(self, acl: Acl) -> Self {
+        TftpServerBuilder {
+            rrq_acl: acl,
+            ..self
+        }
+    }
+
+    /// Set which clients can send write requests.
+    ///
+    /// See [`rrq_acl`](Self::rrq_acl).
+    pub fn wrq_acl(self, acl: Acl) -> Self {
+        TftpServerBuilder {
+            wrq_acl: acl,
+            ..self
+        }
+    }
+
+    /// Ignore requests of denied clients instead of replying with an error.
+    pub fn drop_denied_requests(self) -> Self {
+        TftpServerBuilder {
+            drop_denied: true,
+            ..self
+        }
+    }
+
+    /// Set maximum number of Unknown transfer ID errors that each transfer
+    /// sends per second.
+    ///
+    /// Transfer replies with this error to packets that arrive from other
+    /// ports than its client (RFC1350), and continues. Set it to `0` to
+    /// ignore these packets. This has no effect in
+    /// [`single_port`](Self::single_port) mode, where such packets are not
+    /// delivered to transfers.
+    ///
+    /// **Default:** Unlimited
+    pub fn unknown_tid_reply_limit(self, limit: u32) -> Self {
+        TftpServerBuilder {
+            unknown_tid_reply_limit: Some(limit),
+            ..self
+        }
+    }
+
+    /// Set maximum size of the files that clients can write.
+    ///
+    /// Write requests with a bigger `tsize` option (RFC2349) are rejected
+    /// with [`OptionNegotiation`](crate::packet::Error::OptionNegotiation)
+    /// error. Size of requests without the option is not known in advance,
+    /// so handlers need to enforce it while writing.
+    ///
+    /// **Default:** Any size
+    pub fn max_write_size(self, size: u64) -> Self {
+        TftpServerBuilder {
+            max_write_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Set observer that receives the events of all transfers.
+    pub fn observer<O>(self, observer: O) -> Self
+    where
+        O: Observer + 'static,
+    {
+        TftpServerBuilder {
+            observer: Some(Arc::new(observer)),
+            ..self
+        }
+    }
+
+    /// Serve the statistics of the server in the text format of Prometheus,
+    /// over HTTP on `GET /metrics` of `addr`.
+    ///
+    /// HTTP server runs on the same executor as [`TftpServer::serve`].
+    ///
+    /// **Default:** Disabled
+    #[cfg(feature = "prometheus")]
+    pub fn metrics_addr(self, addr: SocketAddr) -> Self {
+        TftpServerBuilder {
+            metrics_addr: Some(addr),
+            ..self
+        }
+    }
+
     /// Build [`TftpServer`].
-    pub async fn build(mut self) -> Result<TftpServer<H>> {
-        let socket = match self.socket.take() {
-            Some(socket) => socket,
-            None => Async::<UdpSocket>::bind(self.addr).map_err(Error::Bind)?,
+    pub async fn build(self) -> Result<TftpServer<H>> {
+        let sockets = if self.sockets.is_empty() {
+            let v6_only = self.addrs.iter().any(SocketAddr::is_ipv4);
+
+            self.addrs
+                .iter()
+                .map(|addr| bind_listener(*addr, v6_only))
+                .collect::<io::Result<Vec<_>>>()
+                .map_err(Error::Bind)?
+        } else {
+            self.sockets
+        };
+
+        if sockets.is_empty() {
+            let e = io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No listening address",
+            );
+            return Err(Error::Bind(e));
+        }
+
+        let listeners = sockets
+            .into_iter()
+            .map(|socket| {
+                let local_addr = socket.get_ref().local_addr()?;
+
+                // Learn the local address of each request, so transfers
+                // reply from it. Without it kernel picks the address.
+                let pktinfo = local_addr.ip().is_unspecified()
+                    && match pktinfo::enable(socket.get_ref()) {
+                        Ok(enabled) => enabled,
+                        Err(e) => {
+                            trace!(
+                                "Failed to enable pktinfo on {}: {}",
+                                local_addr,
+                                e
+                            );
+                            false
+                        }
+                    };
+
+                Ok(Listener {
+                    socket: Arc::new(socket),
+                    local_addr,
+                    pktinfo,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        #[cfg(feature = "prometheus")]
+        let metrics = match self.metrics_addr {
+            Some(addr) => {
+                Some(Async::<TcpListener>::bind(addr).map_err(Error::Bind)?)
+            }
+            None => None,
         };
 
         let config = ServerConfig {
             timeout: self.timeout,
+            adaptive_timeout: self.adaptive_timeout,
             block_size_limit: self.block_size_limit,
+            min_block_size: self.min_block_size,
+            window_size_limit: self.window_size_limit,
             max_send_retries: self.max_send_retries,
             ignore_client_timeout: self.ignore_client_timeout,
             ignore_client_block_size: self.ignore_client_block_size,
+            single_port: self.single_port,
+            port_range: self.port_range.map(PortRange::new),
+            limits: TransferLimits::new(
+                self.max_transfers,
+                self.max_transfers_per_ip,
+                self.transfer_queue_timeout,
+            ),
+            throttle: Throttle::new(
+                self.rate_limit_per_transfer,
+                self.rate_limit_per_ip,
+                self.rate_limit_total,
+            ),
+            rrq_acl: self.rrq_acl,
+            wrq_acl: self.wrq_acl,
+            drop_denied: self.drop_denied,
+            unknown_tid_reply_limit: self.unknown_tid_reply_limit,
+            max_write_size: self.max_write_size,
+            observer: self.observer,
+            stats: Arc::default(),
         };
 
-        let local_ip = socket.as_ref().local_addr()?.ip();
+        let (shutdown_tx, shutdown_rx) = async_channel::bounded(1);
+        let (cancel_tx, cancel_rx) = async_channel::bounded(1);
+        // Notifications of finished requests are coalesced, waiters check
+        // `reqs_in_progress` after each one.
+        let (req_done_tx, req_done_rx) = async_channel::bounded(1);
+
         Ok(TftpServer {
-            socket,
-            handler: Arc::new(Mutex::new(self.handle)),
-            reqs_in_progress: Arc::new(Mutex::new(HashSet::new())),
+            listeners,
+            handler: self.handler,
+            reqs_in_progress: Arc::new(Mutex::new(HashMap::new())),
             ex: Executor::new(),
             config,
-            local_ip,
+            shutdown_tx,
+            shutdown_rx,
+            cancel_tx,
+            cancel_rx,
+            req_done_tx,
+            req_done_rx,
+            #[cfg(feature = "prometheus")]
+            metrics,
         })
     }
 }
+
+/// Bind a listening socket on `addr`, limited to IPv6 if `v6_only` is set
+/// and `addr` is an IPv6 address.
+fn bind_listener(
+    addr: SocketAddr,
+    v6_only: bool,
+) -> io::Result<Async<UdpSocket>> {
+    let domain = Domain::for_address(addr);
+    let socket = Socket::new(domain, Type::DGRAM, Some(Protocol::UDP))?;
+
+    if v6_only && addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+
+    socket.bind(&addr.into())?;
+    Async::new(socket.into())
+}
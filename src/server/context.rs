@@ -0,0 +1,122 @@
+#[cfg(unix)]
+use std::ffi::OsStr;
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::packet::{Mode, Opts, RwReq};
+
+/// Context of a read or write request, given to [`Handler`].
+///
+/// [`Handler`]: super::Handler
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    peer: SocketAddr,
+    local_addr: SocketAddr,
+    mode: Mode,
+    opts: Opts,
+    filename: String,
+    filename_bytes: Vec<u8>,
+}
+
+impl RequestContext {
+    pub(crate) fn new(
+        peer: SocketAddr,
+        local_addr: SocketAddr,
+        req: &RwReq,
+    ) -> Self {
+        RequestContext {
+            peer,
+            local_addr,
+            mode: req.mode,
+            opts: req.opts.clone(),
+            filename: String::from_utf8_lossy(&req.filename).into_owned(),
+            filename_bytes: req.filename.clone(),
+        }
+    }
+
+    /// Address of the client.
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Local address that received the request.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Transfer mode. This is either `Octet` or `Netascii`, data of the
+    /// latter are translated by the server.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Options requested by the client. Server may negotiate different
+    /// values, depending on its configuration.
+    pub fn opts(&self) -> &Opts {
+        &self.opts
+    }
+
+    /// Filename as it was requested by the client.
+    ///
+    /// Invalid UTF-8 sequences are replaced with `U+FFFD`. Use
+    /// [`filename_bytes`](Self::filename_bytes) for the original bytes.
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Filename as it was requested by the client, in raw bytes.
+    pub fn filename_bytes(&self) -> &[u8] {
+        &self.filename_bytes
+    }
+
+    /// Path that is given to [`Handler`]. On Unix it is made of the raw
+    /// bytes of the filename.
+    ///
+    /// [`Handler`]: super::Handler
+    pub(crate) fn path(&self) -> &Path {
+        #[cfg(unix)]
+        return Path::new(OsStr::from_bytes(&self.filename_bytes));
+
+        #[cfg(not(unix))]
+        return Path::new(&self.filename);
+    }
+}
+
+/// Outcome of a transfer, given to [`Handler`] when the transfer finishes.
+///
+/// [`Handler`]: super::Handler
+#[derive(Debug)]
+pub struct TransferOutcome {
+    bytes: u64,
+    result: Result<()>,
+}
+
+impl TransferOutcome {
+    pub(crate) fn new(bytes: u64, result: Result<()>) -> Self {
+        TransferOutcome {
+            bytes,
+            result,
+        }
+    }
+
+    /// Number of data bytes that were transferred, as they were sent over
+    /// the network. Bytes of read requests are counted when client
+    /// acknowledges them.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns `true` if all data were transferred. For write requests
+    /// this also means that `Writer` was closed successfully.
+    pub fn is_success(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    /// Returns the error that terminated the transfer.
+    pub fn error(&self) -> Option<&Error> {
+        self.result.as_ref().err()
+    }
+}
@@ -1,10 +1,22 @@
+use async_lock::Mutex;
 use futures_lite::{AsyncRead, AsyncWrite};
-use std::net::SocketAddr;
 use std::path::Path;
+use std::sync::Arc;
 
+use super::{RequestContext, TransferOutcome};
 use crate::packet;
 
 /// Trait for implementing advance handlers.
+///
+/// Server serializes the calls of a `Handler`, so a slow open delays the
+/// rest of the requests. Implement [`SharedHandler`] if requests need to
+/// be opened concurrently.
+///
+/// Open methods can reject the options of [`RequestContext::opts`] with
+/// [`packet::Error::OptionNegotiation`].
+///
+/// Shutdown of the server waits for the `*_finished` hooks, also after its
+/// grace period, so they should not block for long.
 #[crate::async_trait]
 pub trait Handler: Send {
     type Reader: AsyncRead + Unpin + Send + 'static;
@@ -13,15 +25,246 @@ pub trait Handler: Send {
     /// Open `Reader` to serve a read request.
     async fn read_req_open(
         &mut self,
-        client: &SocketAddr,
+        ctx: &RequestContext,
         path: &Path,
     ) -> Result<(Self::Reader, Option<u64>), packet::Error>;
 
     /// Open `Writer` to serve a write request.
     async fn write_req_open(
         &mut self,
-        client: &SocketAddr,
+        ctx: &RequestContext,
         path: &Path,
         size: Option<u64>,
     ) -> Result<Self::Writer, packet::Error>;
+
+    /// Called when a read request that was opened by `read_req_open`
+    /// finishes, successfully or not.
+    async fn read_req_finished(
+        &mut self,
+        _ctx: &RequestContext,
+        _outcome: &TransferOutcome,
+    ) {
+    }
+
+    /// Called when a write request that was opened by `write_req_open`
+    /// finishes, successfully or not. `Writer` is already dropped.
+    async fn write_req_finished(
+        &mut self,
+        _ctx: &RequestContext,
+        _outcome: &TransferOutcome,
+    ) {
+    }
+}
+
+/// Trait for implementing handlers that open requests concurrently.
+///
+/// Use it with [`TftpServerBuilder::with_shared_handler`]. Every `Arc` of
+/// a `SharedHandler` is also a [`Handler`].
+///
+/// Like with [`Handler`], shutdown waits for the `*_finished` hooks.
+///
+/// [`TftpServerBuilder::with_shared_handler`]: super::TftpServerBuilder::with_shared_handler
+#[crate::async_trait]
+pub trait SharedHandler: Send + Sync {
+    type Reader: AsyncRead + Unpin + Send + 'static;
+    type Writer: AsyncWrite + Unpin + Send + 'static;
+
+    /// Open `Reader` to serve a read request.
+    async fn read_req_open(
+        &self,
+        ctx: &RequestContext,
+        path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error>;
+
+    /// Open `Writer` to serve a write request.
+    async fn write_req_open(
+        &self,
+        ctx: &RequestContext,
+        path: &Path,
+        size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error>;
+
+    /// Called when a read request that was opened by `read_req_open`
+    /// finishes, successfully or not.
+    async fn read_req_finished(
+        &self,
+        _ctx: &RequestContext,
+        _outcome: &TransferOutcome,
+    ) {
+    }
+
+    /// Called when a write request that was opened by `write_req_open`
+    /// finishes, successfully or not. `Writer` is already dropped.
+    async fn write_req_finished(
+        &self,
+        _ctx: &RequestContext,
+        _outcome: &TransferOutcome,
+    ) {
+    }
+}
+
+#[crate::async_trait]
+impl<S> Handler for Arc<S>
+where
+    S: SharedHandler,
+{
+    type Reader = S::Reader;
+    type Writer = S::Writer;
+
+    async fn read_req_open(
+        &mut self,
+        ctx: &RequestContext,
+        path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        S::read_req_open(self, ctx, path).await
+    }
+
+    async fn write_req_open(
+        &mut self,
+        ctx: &RequestContext,
+        path: &Path,
+        size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error> {
+        S::write_req_open(self, ctx, path, size).await
+    }
+
+    async fn read_req_finished(
+        &mut self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    ) {
+        S::read_req_finished(self, ctx, outcome).await
+    }
+
+    async fn write_req_finished(
+        &mut self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    ) {
+        S::write_req_finished(self, ctx, outcome).await
+    }
+}
+
+/// Handler as it is stored by the server. Exclusive handlers are behind
+/// a mutex, shared ones are called directly.
+pub(crate) type HandlerRef<H> = Arc<
+    dyn OpenReq<
+        Reader = <H as Handler>::Reader,
+        Writer = <H as Handler>::Writer,
+    >,
+>;
+
+#[crate::async_trait]
+pub(crate) trait OpenReq: Send + Sync {
+    type Reader;
+    type Writer;
+
+    async fn read_req_open(
+        &self,
+        ctx: &RequestContext,
+        path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error>;
+
+    async fn write_req_open(
+        &self,
+        ctx: &RequestContext,
+        path: &Path,
+        size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error>;
+
+    async fn read_req_finished(
+        &self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    );
+
+    async fn write_req_finished(
+        &self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    );
+}
+
+#[crate::async_trait]
+impl<H> OpenReq for Mutex<H>
+where
+    H: Handler,
+{
+    type Reader = H::Reader;
+    type Writer = H::Writer;
+
+    async fn read_req_open(
+        &self,
+        ctx: &RequestContext,
+        path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        self.lock().await.read_req_open(ctx, path).await
+    }
+
+    async fn write_req_open(
+        &self,
+        ctx: &RequestContext,
+        path: &Path,
+        size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error> {
+        self.lock().await.write_req_open(ctx, path, size).await
+    }
+
+    async fn read_req_finished(
+        &self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    ) {
+        self.lock().await.read_req_finished(ctx, outcome).await
+    }
+
+    async fn write_req_finished(
+        &self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    ) {
+        self.lock().await.write_req_finished(ctx, outcome).await
+    }
+}
+
+#[crate::async_trait]
+impl<S> OpenReq for S
+where
+    S: SharedHandler,
+{
+    type Reader = S::Reader;
+    type Writer = S::Writer;
+
+    async fn read_req_open(
+        &self,
+        ctx: &RequestContext,
+        path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        S::read_req_open(self, ctx, path).await
+    }
+
+    async fn write_req_open(
+        &self,
+        ctx: &RequestContext,
+        path: &Path,
+        size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error> {
+        S::write_req_open(self, ctx, path, size).await
+    }
+
+    async fn read_req_finished(
+        &self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    ) {
+        S::read_req_finished(self, ctx, outcome).await
+    }
+
+    async fn write_req_finished(
+        &self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    ) {
+        S::write_req_finished(self, ctx, outcome).await
+    }
 }
@@ -1,19 +1,51 @@
 use blocking::{unblock, Unblock};
+use futures_lite::future::Boxed;
+use futures_lite::{ready, AsyncWrite};
 use log::trace;
-use std::fs::{self, File};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
 use std::io;
 use std::net::SocketAddr;
 use std::path::Component;
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
 use crate::error::{Error, Result};
 use crate::packet;
+use crate::server::{RequestContext, TransferOutcome};
 
 /// Handler that serves read requests for a directory.
+///
+/// Write requests are stored in a temporary file, which is moved to the
+/// requested path when [`UploadWriter`] is closed. Server closes it before
+/// it acknowledges the last block, so client learns if this fails.
+///
+/// Temporary files are named `.<file>.<N>.tmp` and are never served, nor
+/// can they be uploaded. If the process stops during an upload, its
+/// temporary file is left behind in the directory.
 pub struct DirHandler {
     dir: PathBuf,
     serve_rrq: bool,
     serve_wrq: bool,
+    overwrite_policy: OverwritePolicy,
+    // Write requests in progress, by client.
+    uploads: HashMap<SocketAddr, Upload>,
+}
+
+#[derive(Clone)]
+struct Upload {
+    path: PathBuf,
+    tmp_path: PathBuf,
+}
+
+/// Writer of [`DirHandler`] that stores an upload when it is closed.
+pub struct UploadWriter {
+    file: Unblock<File>,
+    // Taken when storing starts.
+    upload: Option<(Upload, OverwritePolicy)>,
+    store: Option<Boxed<io::Result<PathBuf>>>,
 }
 
 pub enum DirHandlerMode {
@@ -25,6 +57,23 @@ pub enum DirHandlerMode {
     ReadWrite,
 }
 
+/// What [`DirHandler`] does when a write request targets an existing file.
+///
+/// `NoOverwrite` and `Versioned` store uploads with hard links, which fail
+/// if the destination exists. On filesystems without hard links, such as
+/// FAT or some network filesystems, uploads are copied to an exclusively
+/// created file instead, so readers may see them before they are complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Replace the existing file. This is the default.
+    Overwrite,
+    /// Reject the request with `FileAlreadyExists` error.
+    NoOverwrite,
+    /// Keep the existing file and store the new one as `file.1`, `file.2`,
+    /// etc, whichever is available first.
+    Versioned,
+}
+
 impl DirHandler {
     /// Create new handler for directory.
     pub fn new<P>(dir: P, flags: DirHandlerMode) -> Result<Self>
@@ -55,18 +104,30 @@ impl DirHandler {
             dir,
             serve_rrq,
             serve_wrq,
+            overwrite_policy: OverwritePolicy::Overwrite,
+            uploads: HashMap::new(),
         })
     }
+
+    /// Set what happens when a write request targets an existing file.
+    ///
+    /// **Default:** [`OverwritePolicy::Overwrite`]
+    pub fn overwrite_policy(self, policy: OverwritePolicy) -> Self {
+        DirHandler {
+            overwrite_policy: policy,
+            ..self
+        }
+    }
 }
 
 #[crate::async_trait]
 impl crate::server::Handler for DirHandler {
     type Reader = Unblock<File>;
-    type Writer = Unblock<File>;
+    type Writer = UploadWriter;
 
     async fn read_req_open(
         &mut self,
-        _client: &SocketAddr,
+        _ctx: &RequestContext,
         path: &Path,
     ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
         if !self.serve_rrq {
@@ -75,8 +136,8 @@ impl crate::server::Handler for DirHandler {
 
         let path = secure_path(&self.dir, path)?;
 
-        // Send only regular files
-        if !path.is_file() {
+        // Send only regular files, but not partial uploads
+        if !path.is_file() || is_tmp_file(&path) {
             return Err(packet::Error::FileNotFound);
         }
 
@@ -91,9 +152,9 @@ impl crate::server::Handler for DirHandler {
 
     async fn write_req_open(
         &mut self,
-        _client: &SocketAddr,
+        ctx: &RequestContext,
         path: &Path,
-        size: Option<u64>,
+        _size: Option<u64>,
     ) -> Result<Self::Writer, packet::Error> {
         if !self.serve_wrq {
             return Err(packet::Error::IllegalOperation);
@@ -101,13 +162,111 @@ impl crate::server::Handler for DirHandler {
 
         let path = secure_path(&self.dir, path)?;
 
+        if is_tmp_file(&path) {
+            return Err(packet::Error::PermissionDenied);
+        }
+
+        // This is checked again when the upload is stored, because another
+        // upload of the same file may finish first. But it is better to fail
+        // before the transfer starts.
+        if self.overwrite_policy == OverwritePolicy::NoOverwrite
+            && path.exists()
+        {
+            return Err(packet::Error::FileAlreadyExists);
+        }
+
         let path_clone = path.clone();
-        let file = unblock(move || open_file_wo(path_clone, size)).await?;
-        let writer = Unblock::new(file);
+        let (file, tmp_path) =
+            unblock(move || create_tmp_file(&path_clone)).await?;
+
+        trace!(
+            "TFTP receiving file: {} (temporary file: {})",
+            path.display(),
+            tmp_path.display()
+        );
+
+        let upload = Upload {
+            path,
+            tmp_path,
+        };
+
+        self.uploads.insert(ctx.peer(), upload.clone());
+
+        Ok(UploadWriter {
+            file: Unblock::new(file),
+            upload: Some((upload, self.overwrite_policy)),
+            store: None,
+        })
+    }
+
+    async fn write_req_finished(
+        &mut self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    ) {
+        let upload = match self.uploads.remove(&ctx.peer()) {
+            Some(upload) => upload,
+            None => return,
+        };
+
+        // `UploadWriter` already stored successful uploads.
+        if outcome.is_success() {
+            return;
+        }
+
+        let path = upload.path.clone();
+        trace!("TFTP discarded file: {}", path.display());
+
+        if let Err(e) = unblock(move || discard_upload(upload)).await {
+            trace!("TFTP failed to discard file {}: {}", path.display(), e)
+        }
+    }
+}
+
+impl AsyncWrite for UploadWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.file).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.file).poll_flush(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.store.is_none() {
+            // Flushes and closes the temporary file
+            ready!(Pin::new(&mut this.file).poll_close(cx))?;
 
-        trace!("TFTP receiving file: {}", path.display());
+            let (upload, policy) = match this.upload.take() {
+                Some(upload) => upload,
+                // Already stored
+                None => return Poll::Ready(Ok(())),
+            };
 
-        Ok(writer)
+            this.store =
+                Some(Box::pin(unblock(move || store_upload(upload, policy))));
+        }
+
+        let store = this.store.as_mut().expect("store is set above");
+        let res = ready!(store.as_mut().poll(cx));
+        this.store = None;
+
+        let stored_path = res?;
+        trace!("TFTP received file: {}", stored_path.display());
+
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -142,12 +301,116 @@ fn open_file_ro(path: PathBuf) -> io::Result<(File, Option<u64>)> {
     Ok((file, len))
 }
 
-fn open_file_wo(path: PathBuf, size: Option<u64>) -> io::Result<File> {
-    let file = File::create(path)?;
+/// Create a new temporary file next to `path`.
+fn create_tmp_file(path: &Path) -> io::Result<(File, PathBuf)> {
+    let name = path.file_name().unwrap_or_default();
+
+    for i in 0..100 {
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(name);
+        tmp_name.push(format!(".{}.tmp", i));
+
+        let tmp_path = path.with_file_name(tmp_name);
+
+        match OpenOptions::new().write(true).create_new(true).open(&tmp_path) {
+            Ok(file) => return Ok((file, tmp_path)),
+            // Another upload of the same file is in progress
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(io::ErrorKind::AlreadyExists.into())
+}
+
+/// Returns `true` if `path` is named like the files of `create_tmp_file`.
+fn is_tmp_file(path: &Path) -> bool {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    let stem = match name.strip_prefix('.').and_then(|n| n.strip_suffix(".tmp"))
+    {
+        Some(stem) => stem,
+        None => return false,
+    };
+
+    match stem.rsplit_once('.') {
+        Some((_, n)) => !n.is_empty() && n.bytes().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Move the temporary file into place, according to `policy`.
+///
+/// Returns the path where upload was stored.
+fn store_upload(
+    upload: Upload,
+    policy: OverwritePolicy,
+) -> io::Result<PathBuf> {
+    let res = match policy {
+        OverwritePolicy::Overwrite => {
+            fs::rename(&upload.tmp_path, &upload.path)
+                .map(|_| upload.path.clone())
+        }
+        // Unlike `rename`, this fails if the destination exists.
+        OverwritePolicy::NoOverwrite => {
+            link_or_copy(&upload.tmp_path, &upload.path)
+                .map(|_| upload.path.clone())
+        }
+        OverwritePolicy::Versioned => link_versioned(&upload),
+    };
+
+    // After a rename this fails, which is fine.
+    let _ = fs::remove_file(&upload.tmp_path);
+
+    res
+}
+
+fn discard_upload(upload: Upload) -> io::Result<()> {
+    match fs::remove_file(&upload.tmp_path) {
+        // `store_upload` already removed it
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        res => res,
+    }
+}
+
+/// Hard link `from` to `to`, or copy it if the filesystem does not support
+/// hard links. Fails with `AlreadyExists` if `to` exists.
+fn link_or_copy(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::hard_link(from, to) {
+        Ok(()) => return Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => return Err(e),
+        Err(e) => trace!("Failed to link {}: {}", to.display(), e),
+    }
+
+    let mut src = File::open(from)?;
+    let mut dest = OpenOptions::new().write(true).create_new(true).open(to)?;
+
+    if let Err(e) = io::copy(&mut src, &mut dest) {
+        drop(dest);
+        let _ = fs::remove_file(to);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Link the temporary file to the requested path, or to the first
+/// available versioned name of it.
+fn link_versioned(upload: &Upload) -> io::Result<PathBuf> {
+    let name = upload.path.file_name().unwrap_or_default();
+    let mut path = upload.path.clone();
+
+    for version in 1.. {
+        match link_or_copy(&upload.tmp_path, &path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
 
-    if let Some(size) = size {
-        file.set_len(size)?;
+        let mut versioned_name = name.to_owned();
+        versioned_name.push(format!(".{}", version));
+        path.set_file_name(versioned_name);
     }
 
-    Ok(file)
+    Err(io::ErrorKind::AlreadyExists.into())
 }
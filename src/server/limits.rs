@@ -0,0 +1,111 @@
+use async_io::Timer;
+use async_lock::{Semaphore, SemaphoreGuardArc};
+use futures_lite::future;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, Result};
+
+/// Limits of concurrent transfers.
+#[derive(Clone)]
+pub(crate) struct TransferLimits {
+    total: Option<Arc<Semaphore>>,
+    per_ip: Option<usize>,
+    // Semaphores of the clients that have transfers in progress or queued.
+    clients: Arc<Mutex<HashMap<IpAddr, Arc<Semaphore>>>>,
+    queue_timeout: Duration,
+}
+
+/// Permission of a transfer to run. Limits are released on drop.
+pub(crate) struct TransferPermit {
+    ip: IpAddr,
+    clients: Arc<Mutex<HashMap<IpAddr, Arc<Semaphore>>>>,
+    client_sem: Option<Arc<Semaphore>>,
+    client: Option<SemaphoreGuardArc>,
+    total: Option<SemaphoreGuardArc>,
+}
+
+impl TransferLimits {
+    pub(crate) fn new(
+        total: Option<usize>,
+        per_ip: Option<usize>,
+        queue_timeout: Duration,
+    ) -> Self {
+        TransferLimits {
+            total: total.map(|max| Arc::new(Semaphore::new(max))),
+            per_ip,
+            clients: Arc::new(Mutex::new(HashMap::new())),
+            queue_timeout,
+        }
+    }
+
+    /// Wait until a transfer of `ip` is allowed to run.
+    ///
+    /// Fails with [`Error::TooManyTransfers`] if it is not allowed within the
+    /// queue timeout.
+    pub(crate) async fn acquire(&self, ip: IpAddr) -> Result<TransferPermit> {
+        let deadline = Instant::now() + self.queue_timeout;
+
+        let client_sem = self.per_ip.map(|max| {
+            let mut clients = self.clients.lock().unwrap();
+            let sem = clients
+                .entry(ip)
+                .or_insert_with(|| Arc::new(Semaphore::new(max)));
+            Arc::clone(sem)
+        });
+
+        // Permits are released by `drop` if we fail
+        let mut permit = TransferPermit {
+            ip,
+            clients: Arc::clone(&self.clients),
+            client_sem,
+            client: None,
+            total: None,
+        };
+
+        // Client waits for its own limit first, so its queued requests do
+        // not hold the permits of other clients.
+        if let Some(sem) = &permit.client_sem {
+            permit.client = Some(wait(sem, deadline).await?);
+        }
+
+        if let Some(sem) = &self.total {
+            permit.total = Some(wait(sem, deadline).await?);
+        }
+
+        Ok(permit)
+    }
+}
+
+async fn wait(
+    sem: &Arc<Semaphore>,
+    deadline: Instant,
+) -> Result<SemaphoreGuardArc> {
+    if let Some(guard) = sem.try_acquire_arc() {
+        return Ok(guard);
+    }
+
+    future::or(async { Ok(sem.acquire_arc().await) }, async {
+        Timer::at(deadline).await;
+        Err(Error::TooManyTransfers)
+    })
+    .await
+}
+
+impl Drop for TransferPermit {
+    fn drop(&mut self) {
+        self.total.take();
+        self.client.take();
+
+        if let Some(sem) = self.client_sem.take() {
+            let mut clients = self.clients.lock().unwrap();
+
+            // Forget the client if nobody else uses its semaphore
+            if Arc::strong_count(&sem) == 2 {
+                clients.remove(&self.ip);
+            }
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use async_executor::Executor;
+use async_io::{Async, Timer};
+use futures_lite::{future, AsyncReadExt, AsyncWriteExt};
+use log::trace;
+use std::fmt::Write;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::stats::{ServerStats, StatsCollector};
+use crate::error::Result;
+
+/// Maximum size of an HTTP request head.
+const MAX_REQUEST_LEN: usize = 8192;
+
+/// Time that a client has for sending its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Delay before accepting again after an error, such as running out of
+/// file descriptors.
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_millis(100);
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+impl ServerStats {
+    /// Render the statistics in the text exposition format of Prometheus.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        gauge(
+            &mut out,
+            "tftp_active_transfers",
+            "Transfers in progress.",
+            self.active_transfers,
+        );
+
+        header(
+            &mut out,
+            "tftp_transfers_total",
+            "Finished transfers.",
+            "counter",
+        );
+        let _ = writeln!(
+            out,
+            "tftp_transfers_total{{result=\"completed\"}} {}",
+            self.completed_transfers
+        );
+        let _ = writeln!(
+            out,
+            "tftp_transfers_total{{result=\"failed\"}} {}",
+            self.failed_transfers
+        );
+
+        counter(
+            &mut out,
+            "tftp_sent_bytes_total",
+            "Data bytes sent for read requests.",
+            self.bytes_sent,
+        );
+        counter(
+            &mut out,
+            "tftp_received_bytes_total",
+            "Data bytes received for write requests.",
+            self.bytes_received,
+        );
+        counter(
+            &mut out,
+            "tftp_retransmissions_total",
+            "Packets that were sent again.",
+            self.retransmissions,
+        );
+        counter(
+            &mut out,
+            "tftp_timeouts_total",
+            "Times that a client did not reply in time.",
+            self.timeouts,
+        );
+
+        header(
+            &mut out,
+            "tftp_errors_total",
+            "Errors sent to clients, by code.",
+            "counter",
+        );
+        for (code, count) in &self.errors {
+            let _ = writeln!(
+                out,
+                "tftp_errors_total{{code=\"{}\"}} {}",
+                code, count
+            );
+        }
+
+        let durations = &self.transfer_durations;
+        header(
+            &mut out,
+            "tftp_transfer_duration_seconds",
+            "Durations of the finished transfers.",
+            "histogram",
+        );
+        for (bound, count) in durations.buckets() {
+            let _ = writeln!(
+                out,
+                "tftp_transfer_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound.as_secs_f64(),
+                count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "tftp_transfer_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            durations.count()
+        );
+        let _ = writeln!(
+            out,
+            "tftp_transfer_duration_seconds_sum {}",
+            durations.sum().as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "tftp_transfer_duration_seconds_count {}",
+            durations.count()
+        );
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Serve the statistics on `GET /metrics` of every connection of `listener`.
+pub(crate) async fn serve(
+    listener: &Async<TcpListener>,
+    stats: &Arc<StatsCollector>,
+    ex: &Executor<'static>,
+) -> Result<()> {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                trace!("Failed to accept metrics connection: {}", e);
+                // Errors usually persist for a while, do not spin on them
+                Timer::after(ACCEPT_ERROR_DELAY).await;
+                continue;
+            }
+        };
+
+        let stats = Arc::clone(stats);
+
+        ex.spawn(async move {
+            if let Err(e) = handle_conn(stream, &stats).await {
+                trace!("Metrics connection failed (peer: {}): {}", peer, e);
+            }
+        })
+        .detach();
+    }
+}
+
+async fn handle_conn(
+    mut stream: Async<TcpStream>,
+    stats: &StatsCollector,
+) -> io::Result<()> {
+    let read = read_request_head(&mut stream);
+    let timeout = async {
+        Timer::after(REQUEST_TIMEOUT).await;
+        Err(io::ErrorKind::TimedOut.into())
+    };
+    let head = future::or(read, timeout).await?;
+
+    let mut parts = head.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default();
+
+    let response = match (method, path) {
+        ("GET", "/metrics") => {
+            response("200 OK", &stats.snapshot().to_prometheus())
+        }
+        ("GET", _) => response("404 Not Found", "Not Found\n"),
+        _ => response("405 Method Not Allowed", "Method Not Allowed\n"),
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await
+}
+
+/// Read the request line and headers. Request body is ignored.
+async fn read_request_head(
+    stream: &mut Async<TcpStream>,
+) -> io::Result<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        if head.len() >= MAX_REQUEST_LEN {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+
+        let len = stream.read(&mut buf).await?;
+
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        head.extend_from_slice(&buf[..len]);
+    }
+
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+fn response(status: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )
+}
@@ -1,14 +1,29 @@
 //! Server side implementation.
 
+mod acl;
 mod builder;
+mod context;
 mod handler;
+mod limits;
+#[cfg(feature = "prometheus")]
+mod metrics;
+mod observer;
+mod pktinfo;
 mod read_req;
+mod rto;
 #[allow(clippy::module_inception)]
 mod server;
+mod socket;
+mod stats;
+mod throttle;
 mod write_req;
 
 pub mod handlers;
 
+pub use self::acl::*;
 pub use self::builder::*;
+pub use self::context::*;
 pub use self::handler::*;
+pub use self::observer::{Observer, TransferEvent};
 pub use self::server::*;
+pub use self::stats::{DurationHistogram, ServerStats};
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::stats::StatsCollector;
+use super::{RequestContext, TransferOutcome};
+use crate::error::Error;
+use crate::packet::{self, Opts};
+
+/// Observer of the transfers of a server.
+///
+/// It is called from within the transfers, so it must not block. Use it
+/// for statistics and progress reports, and forward the events to another
+/// task if they need more work.
+pub trait Observer: Send + Sync {
+    /// Called on each event of the transfer of `ctx`.
+    fn on_event(&self, ctx: &RequestContext, event: &TransferEvent);
+}
+
+impl<F> Observer for F
+where
+    F: Fn(&RequestContext, &TransferEvent) + Send + Sync,
+{
+    fn on_event(&self, ctx: &RequestContext, event: &TransferEvent) {
+        self(ctx, event)
+    }
+}
+
+/// Event of a transfer.
+///
+/// Offsets are positions in the data, as they are sent over the network.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum TransferEvent<'a> {
+    /// Server received the request.
+    Requested,
+    /// Server replied with OACK of these options.
+    Negotiated(&'a Opts),
+    /// Server sent a Data block.
+    BlockSent {
+        block_id: u16,
+        offset: u64,
+        len: usize,
+    },
+    /// Server received a Data block.
+    BlockReceived {
+        block_id: u16,
+        offset: u64,
+        len: usize,
+    },
+    /// Block was acknowledged, either by the client for read requests or
+    /// by the server for write requests. `offset` is the end of the
+    /// acknowledged data.
+    BlockAcked {
+        block_id: u16,
+        offset: u64,
+    },
+    /// Client did not reply in time while server was waiting for
+    /// `block_id`.
+    TimedOut {
+        block_id: u16,
+    },
+    /// Packets were sent again after a timeout or a lost block, starting
+    /// from `block_id`.
+    Retransmitted {
+        block_id: u16,
+    },
+    /// All data were transferred.
+    Completed {
+        bytes: u64,
+    },
+    /// Transfer failed.
+    Failed {
+        bytes: u64,
+        error: &'a Error,
+    },
+}
+
+/// Reports the events of a transfer to the observer and the statistics of
+/// the server.
+#[derive(Clone)]
+pub(crate) struct Events {
+    observer: Option<Arc<dyn Observer>>,
+    stats: Arc<StatsCollector>,
+    ctx: Arc<RequestContext>,
+    started: Instant,
+}
+
+impl Events {
+    pub(crate) fn new(
+        observer: Option<Arc<dyn Observer>>,
+        stats: Arc<StatsCollector>,
+        ctx: Arc<RequestContext>,
+    ) -> Self {
+        Events {
+            observer,
+            stats,
+            ctx,
+            started: Instant::now(),
+        }
+    }
+
+    pub(crate) fn emit(&self, event: TransferEvent) {
+        match event {
+            TransferEvent::Requested => self.stats.transfer_started(),
+            TransferEvent::BlockSent {
+                len,
+                ..
+            } => self.stats.bytes_sent(len),
+            TransferEvent::BlockReceived {
+                len,
+                ..
+            } => self.stats.bytes_received(len),
+            TransferEvent::TimedOut {
+                ..
+            } => self.stats.timeout(),
+            TransferEvent::Retransmitted {
+                ..
+            } => self.stats.retransmission(),
+            // Errors of the peer are not sent
+            TransferEvent::Failed {
+                error: Error::PeerError(..),
+                ..
+            } => {}
+            TransferEvent::Failed {
+                error,
+                ..
+            } => self.stats.error(&packet::Error::from(error)),
+            _ => {}
+        }
+
+        if let Some(observer) = &self.observer {
+            observer.on_event(&self.ctx, &event);
+        }
+    }
+
+    pub(crate) fn finished(&self, outcome: &TransferOutcome) {
+        let bytes = outcome.bytes();
+
+        let duration = self.started.elapsed();
+        self.stats.transfer_finished(outcome.is_success(), duration);
+
+        match outcome.error() {
+            None => self.emit(TransferEvent::Completed {
+                bytes,
+            }),
+            Some(error) => self.emit(TransferEvent::Failed {
+                bytes,
+                error,
+            }),
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Destination address of received packets.
+//!
+//! A socket that is bound to a wildcard address receives the packets of all
+//! local addresses. With `IP_PKTINFO`/`IPV6_RECVPKTINFO` kernel tells us the
+//! local address that each packet was sent to, so transfers can reply from
+//! the same address.
+use async_io::Async;
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+
+pub(crate) use imp::*;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod imp {
+    use std::io;
+    use std::mem;
+    use std::net::{
+        IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6,
+        UdpSocket,
+    };
+    use std::os::unix::io::AsRawFd;
+    use std::ptr;
+
+    /// Enable reporting of destination addresses on `socket`.
+    ///
+    /// Returns `false` if it is not supported.
+    pub(crate) fn enable(socket: &UdpSocket) -> io::Result<bool> {
+        let (level, name) = match socket.local_addr()? {
+            SocketAddr::V4(_) => (libc::IPPROTO_IP, libc::IP_PKTINFO),
+            SocketAddr::V6(_) => (libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO),
+        };
+
+        let on: libc::c_int = 1;
+
+        let ret = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                &on as *const libc::c_int as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(true)
+    }
+
+    /// Receive a packet like `UdpSocket::recv_from` but also return the local
+    /// address it was sent to, if kernel reported it.
+    pub(crate) fn recv_from(
+        socket: &UdpSocket,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr, Option<IpAddr>)> {
+        let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        // `u64` keeps the control messages aligned
+        let mut control = [0u64; 16];
+
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &mut addr as *mut _ as *mut libc::c_void;
+        msg.msg_namelen = mem::size_of_val(&addr) as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        let len = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let peer = unsafe { to_socket_addr(&addr)? };
+        let mut local_ip = None;
+
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+            while !cmsg.is_null() {
+                let data = libc::CMSG_DATA(cmsg);
+
+                match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                    (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                        let info = ptr::read_unaligned(
+                            data as *const libc::in_pktinfo,
+                        );
+                        // For broadcasts this is the address of the
+                        // interface, not the broadcast address.
+                        let ip = u32::from_be(info.ipi_spec_dst.s_addr);
+                        local_ip = Some(IpAddr::V4(Ipv4Addr::from(ip)));
+                    }
+                    (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                        let info = ptr::read_unaligned(
+                            data as *const libc::in6_pktinfo,
+                        );
+                        let ip = Ipv6Addr::from(info.ipi6_addr.s6_addr);
+                        local_ip = Some(IpAddr::V6(ip));
+                    }
+                    _ => {}
+                }
+
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+
+        Ok((len as usize, peer, local_ip))
+    }
+
+    /// Send a packet like `UdpSocket::send_to` but from `local_ip`, which
+    /// matters if `socket` is bound to a wildcard address.
+    pub(crate) fn send_to(
+        socket: &UdpSocket,
+        buf: &[u8],
+        peer: SocketAddr,
+        local_ip: IpAddr,
+    ) -> io::Result<usize> {
+        let mut addr: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let addr_len = unsafe { from_socket_addr(&peer, &mut addr) };
+        // `u64` keeps the control messages aligned
+        let mut control = [0u64; 8];
+
+        let mut iov = libc::iovec {
+            iov_base: buf.as_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = &mut addr as *mut _ as *mut libc::c_void;
+        msg.msg_namelen = addr_len;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+
+        unsafe {
+            match (local_ip, peer) {
+                (IpAddr::V4(ip), SocketAddr::V4(_)) if !ip.is_unspecified() => {
+                    let mut info: libc::in_pktinfo = mem::zeroed();
+                    info.ipi_spec_dst.s_addr = u32::from(ip).to_be();
+                    let (level, kind) = (libc::IPPROTO_IP, libc::IP_PKTINFO);
+                    set_control(&mut msg, &mut control, level, kind, info);
+                }
+                (IpAddr::V6(ip), SocketAddr::V6(_)) if !ip.is_unspecified() => {
+                    let mut info: libc::in6_pktinfo = mem::zeroed();
+                    info.ipi6_addr.s6_addr = ip.octets();
+                    let (level, kind) =
+                        (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO);
+                    set_control(&mut msg, &mut control, level, kind, info);
+                }
+                // Let kernel pick the address
+                _ => {}
+            }
+        }
+
+        let len = unsafe { libc::sendmsg(socket.as_raw_fd(), &msg, 0) };
+
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(len as usize)
+    }
+
+    /// Attach a single control message with `data` to `msg`.
+    unsafe fn set_control<T>(
+        msg: &mut libc::msghdr,
+        control: &mut [u64],
+        level: libc::c_int,
+        kind: libc::c_int,
+        data: T,
+    ) {
+        let len = mem::size_of::<T>() as libc::c_uint;
+
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = libc::CMSG_SPACE(len) as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(msg);
+        (*cmsg).cmsg_level = level;
+        (*cmsg).cmsg_type = kind;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(len) as _;
+        ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut T, data);
+    }
+
+    unsafe fn from_socket_addr(
+        addr: &SocketAddr,
+        storage: &mut libc::sockaddr_storage,
+    ) -> libc::socklen_t {
+        match addr {
+            SocketAddr::V4(addr) => {
+                let sin = &mut *(storage as *mut _ as *mut libc::sockaddr_in);
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = addr.port().to_be();
+                sin.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+                mem::size_of::<libc::sockaddr_in>() as libc::socklen_t
+            }
+            SocketAddr::V6(addr) => {
+                let sin6 = &mut *(storage as *mut _ as *mut libc::sockaddr_in6);
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = addr.port().to_be();
+                sin6.sin6_flowinfo = addr.flowinfo();
+                sin6.sin6_addr.s6_addr = addr.ip().octets();
+                sin6.sin6_scope_id = addr.scope_id();
+                mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t
+            }
+        }
+    }
+
+    unsafe fn to_socket_addr(
+        addr: &libc::sockaddr_storage,
+    ) -> io::Result<SocketAddr> {
+        match addr.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let addr = &*(addr as *const _ as *const libc::sockaddr_in);
+                let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+                let port = u16::from_be(addr.sin_port);
+                Ok(SocketAddr::V4(SocketAddrV4::new(ip, port)))
+            }
+            libc::AF_INET6 => {
+                let addr = &*(addr as *const _ as *const libc::sockaddr_in6);
+                let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+                let port = u16::from_be(addr.sin6_port);
+                Ok(SocketAddr::V6(SocketAddrV6::new(
+                    ip,
+                    port,
+                    addr.sin6_flowinfo,
+                    addr.sin6_scope_id,
+                )))
+            }
+            _ => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod imp {
+    use std::io;
+    use std::net::{IpAddr, SocketAddr, UdpSocket};
+
+    pub(crate) fn enable(_socket: &UdpSocket) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    pub(crate) fn recv_from(
+        socket: &UdpSocket,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr, Option<IpAddr>)> {
+        let (len, peer) = socket.recv_from(buf)?;
+        Ok((len, peer, None))
+    }
+
+    pub(crate) fn send_to(
+        socket: &UdpSocket,
+        buf: &[u8],
+        peer: SocketAddr,
+        _local_ip: IpAddr,
+    ) -> io::Result<usize> {
+        socket.send_to(buf, peer)
+    }
+}
+
+/// Send a packet from `socket` to `peer`, with `local_ip` as its source
+/// address where this is supported.
+pub(crate) async fn send_from(
+    socket: &Async<UdpSocket>,
+    buf: &[u8],
+    peer: SocketAddr,
+    local_ip: IpAddr,
+) -> io::Result<usize> {
+    socket.write_with(|s| send_to(s, buf, peer, local_ip)).await
+}
+
+/// Local address that a transfer of a packet must use.
+///
+/// This is the destination address of the packet if it is known and usable
+/// for binding, otherwise the address of the listening socket.
+pub(crate) fn transfer_local_ip(
+    listen_ip: IpAddr,
+    dest: Option<IpAddr>,
+) -> IpAddr {
+    match dest {
+        // Link-local addresses can not be bound without their scope
+        Some(IpAddr::V6(ip)) if (ip.segments()[0] & 0xffc0) == 0xfe80 => {
+            listen_ip
+        }
+        Some(ip) if ip.is_multicast() || ip.is_unspecified() => listen_ip,
+        Some(ip) => ip,
+        None => listen_ip,
+    }
+}
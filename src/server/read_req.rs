@@ -1,30 +1,51 @@
-use async_io::Async;
+use async_channel::Receiver;
 use bytes::{BufMut, Bytes, BytesMut};
-use futures_lite::{AsyncRead, AsyncReadExt};
+use futures_lite::{future, AsyncRead, AsyncReadExt};
 use log::trace;
 use std::cmp;
+use std::collections::VecDeque;
 use std::io;
-use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::net::SocketAddr;
 use std::slice;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use super::observer::{Events, TransferEvent};
+use super::rto::Rto;
+use super::socket::{TransferSocket, UnknownTidReplies};
+use super::throttle::TransferThrottle;
 use crate::error::{Error, Result};
-use crate::packet::{Opts, Packet, RwReq, PACKET_DATA_HEADER_LEN};
-use crate::server::{ServerConfig, DEFAULT_BLOCK_SIZE};
-use crate::utils::io_timeout;
+use crate::packet::{
+    self, Opts, Packet, RwReq, DEFAULT_BLOCK_SIZE, PACKET_DATA_HEADER_LEN,
+};
+use crate::parse::parse_error_msg;
+use crate::server::{ServerConfig, TransferOutcome};
+use crate::utils::{cancelled, io_timeout};
+
+enum Reply {
+    Ack(u16),
+    Error(packet::Error, String),
+}
 
 pub(crate) struct ReadRequest<'r, R>
 where
     R: AsyncRead + Send,
 {
     peer: SocketAddr,
-    socket: Async<UdpSocket>,
+    socket: TransferSocket,
+    unknown_tid: UnknownTidReplies,
+    throttle: TransferThrottle,
+    events: Events,
     reader: &'r mut R,
     buffer: BytesMut,
     block_size: usize,
+    window_size: usize,
     timeout: Duration,
+    // Used instead of `timeout` if it adapts to the client.
+    rto: Option<Rto>,
     max_send_retries: u32,
     oack_opts: Option<Opts>,
+    // Data bytes acknowledged by the client.
+    bytes: u64,
 }
 
 impl<'r, R> ReadRequest<'r, R>
@@ -37,7 +58,8 @@ where
         peer: SocketAddr,
         req: &RwReq,
         config: ServerConfig,
-        local_ip: IpAddr,
+        socket: TransferSocket,
+        events: Events,
     ) -> Result<ReadRequest<'r, R>> {
         let oack_opts = build_oack_opts(&config, req, file_size);
 
@@ -47,155 +69,307 @@ where
             .map(usize::from)
             .unwrap_or(DEFAULT_BLOCK_SIZE);
 
-        let timeout = oack_opts
+        let window_size = oack_opts
             .as_ref()
-            .and_then(|o| o.timeout)
-            .map(|t| Duration::from_secs(u64::from(t)))
-            .unwrap_or(config.timeout);
+            .and_then(|o| o.window_size)
+            .map(usize::from)
+            .unwrap_or(1);
 
-        let addr = SocketAddr::new(local_ip, 0);
-        let socket = Async::<UdpSocket>::bind(addr).map_err(Error::Bind)?;
+        let negotiated_timeout =
+            oack_opts.as_ref().and_then(Opts::timeout_duration);
+
+        let timeout = negotiated_timeout.unwrap_or(config.timeout);
+
+        let rto = match (negotiated_timeout, config.adaptive_timeout) {
+            (None, Some((min, max))) => Some(Rto::new(timeout, min, max)),
+            _ => None,
+        };
 
         Ok(ReadRequest {
             peer,
             socket,
+            unknown_tid: UnknownTidReplies::new(config.unknown_tid_reply_limit),
+            throttle: config.throttle.transfer(peer.ip()),
+            events,
             reader,
             buffer: BytesMut::with_capacity(
                 PACKET_DATA_HEADER_LEN + block_size,
             ),
             block_size,
+            window_size,
             timeout,
+            rto,
             max_send_retries: config.max_send_retries,
             oack_opts,
+            bytes: 0,
         })
     }
 
-    pub(crate) async fn handle(&mut self) {
-        if let Err(e) = self.try_handle().await {
-            trace!("RRQ request failed (peer: {}, error: {})", &self.peer, &e);
-
-            Packet::Error(e.into()).encode(&mut self.buffer);
-            let buf = self.buffer.split().freeze();
-            // Errors are never retransmitted.
-            // We do not care if `send_to` resulted to an IO error.
-            let _ = self.socket.send_to(&buf[..], self.peer).await;
+    pub(crate) async fn handle(
+        &mut self,
+        cancel: Receiver<()>,
+    ) -> TransferOutcome {
+        let result = future::or(self.try_handle(), cancelled(&cancel)).await;
+
+        if let Err(ref e) = result {
+            trace!("RRQ request failed (peer: {}, error: {})", &self.peer, e);
+
+            // Errors of the client are never replied.
+            if !matches!(e, Error::PeerError(..)) {
+                Packet::Error(e.into()).encode(&mut self.buffer);
+                let buf = self.buffer.split().freeze();
+                // Errors are never retransmitted.
+                // We do not care if `send_to` resulted to an IO error.
+                let _ = self.socket.send_to(&buf[..], self.peer).await;
+            }
         }
+
+        TransferOutcome::new(self.bytes, result)
     }
 
     async fn try_handle(&mut self) -> Result<()> {
         let mut block_id: u16 = 0;
+        let mut last_acked: u16 = 0;
+        let mut is_last_block = false;
+        let mut window = VecDeque::with_capacity(self.window_size);
 
         // Send file to client
         loop {
-            let is_last_block;
+            // Fill window with Data packets
+            while !is_last_block && window.len() < self.window_size {
+                block_id = block_id.wrapping_add(1);
+
+                let (packet, is_last) = self.read_data_packet(block_id).await?;
+                is_last_block = is_last;
+                window.push_back(packet);
+
+                // Send OACK after we manage to read the first block from reader.
+                //
+                // We do this because we want to give the developers the option to
+                // produce an error after they construct a reader.
+                if let Some(opts) = self.oack_opts.take() {
+                    trace!("RRQ OACK (peer: {}, opts: {:?}", &self.peer, &opts);
+
+                    let mut buf = BytesMut::new();
+                    Packet::OAck(opts.to_owned()).encode(&mut buf);
+                    self.events.emit(TransferEvent::Negotiated(&opts));
+
+                    // OACK is acknowledged as block 0.
+                    let oack = [buf.split().freeze()];
+                    self.send(&oack, u16::MAX, None).await?;
+                }
+            }
+
+            if window.is_empty() {
+                break;
+            }
 
-            // Reclaim buffer
-            self.buffer.reserve(PACKET_DATA_HEADER_LEN + self.block_size);
+            // Send window of Data packets and drop the acknowledged ones
+            let offset = Some(self.bytes);
+            let packets = window.make_contiguous();
+            let acked = self.send(packets, last_acked, offset).await?;
 
-            // Encode head of Data packet
-            block_id = block_id.wrapping_add(1);
-            Packet::encode_data_head(block_id, &mut self.buffer);
+            let acked_len = usize::from(acked.wrapping_sub(last_acked));
 
-            // Read block in self.buffer
-            let buf = unsafe {
-                let uninit_buf = self.buffer.chunk_mut();
+            for packet in window.drain(..acked_len) {
+                self.bytes += (packet.len() - PACKET_DATA_HEADER_LEN) as u64;
+            }
 
-                let data_buf = slice::from_raw_parts_mut(
-                    uninit_buf.as_mut_ptr(),
-                    uninit_buf.len(),
-                );
+            last_acked = acked;
 
-                let len = self.read_block(data_buf).await?;
-                is_last_block = len < self.block_size;
+            self.events.emit(TransferEvent::BlockAcked {
+                block_id: acked,
+                offset: self.bytes,
+            });
+        }
 
-                self.buffer.advance_mut(len);
-                self.buffer.split().freeze()
-            };
+        trace!("RRQ request served (peer: {})", &self.peer);
+        Ok(())
+    }
 
-            // Send OACK after we manage to read the first block from reader.
-            //
-            // We do this because we want to give the developers the option to
-            // produce an error after they construct a reader.
-            if let Some(opts) = self.oack_opts.take() {
-                trace!("RRQ OACK (peer: {}, opts: {:?}", &self.peer, &opts);
+    async fn read_data_packet(
+        &mut self,
+        block_id: u16,
+    ) -> Result<(Bytes, bool)> {
+        // Reclaim buffer
+        self.buffer.reserve(PACKET_DATA_HEADER_LEN + self.block_size);
 
-                let mut buf = BytesMut::new();
-                Packet::OAck(opts.to_owned()).encode(&mut buf);
+        // Encode head of Data packet
+        Packet::encode_data_head(block_id, &mut self.buffer);
 
-                self.send(buf.split().freeze(), 0).await?;
-            }
+        // Read block in self.buffer
+        unsafe {
+            let uninit_buf = self.buffer.chunk_mut();
 
-            // Send Data packet
-            self.send(buf, block_id).await?;
+            let data_buf = slice::from_raw_parts_mut(
+                uninit_buf.as_mut_ptr(),
+                uninit_buf.len(),
+            );
 
-            if is_last_block {
-                break;
-            }
-        }
+            let len = self.read_block(data_buf).await?;
+            let is_last_block = len < self.block_size;
 
-        trace!("RRQ request served (peer: {})", &self.peer);
-        Ok(())
+            self.buffer.advance_mut(len);
+            Ok((self.buffer.split().freeze(), is_last_block))
+        }
     }
 
-    async fn send(&mut self, packet: Bytes, block_id: u16) -> Result<()> {
-        // Send packet until we receive an ack
-        for _ in 0..=self.max_send_retries {
-            self.socket.send_to(&packet[..], self.peer).await?;
+    /// Send `packets` until client acknowledges at least one of them.
+    ///
+    /// `packets` must be the consecutive blocks after `last_acked`, and
+    /// `offset` the position of the first one if they are Data packets.
+    /// Returns the last acknowledged block.
+    async fn send(
+        &mut self,
+        packets: &[Bytes],
+        last_acked: u16,
+        offset: Option<u64>,
+    ) -> Result<u16> {
+        for retry in 0..=self.max_send_retries {
+            if retry > 0 {
+                self.events.emit(TransferEvent::Retransmitted {
+                    block_id: last_acked.wrapping_add(1),
+                });
+            }
+
+            let mut block_id = last_acked;
+            let mut offset = offset;
 
-            match self.recv_ack(block_id).await {
-                Ok(_) => {
+            for packet in packets {
+                let len = packet.len() - PACKET_DATA_HEADER_LEN;
+                self.throttle.consume(len).await;
+                self.socket.send_to(&packet[..], self.peer).await?;
+
+                block_id = block_id.wrapping_add(1);
+
+                if let Some(pos) = offset {
+                    self.events.emit(TransferEvent::BlockSent {
+                        block_id,
+                        offset: pos,
+                        len,
+                    });
+
+                    offset = Some(pos + len as u64);
+                }
+            }
+
+            let sent_at = Instant::now();
+
+            match self.recv_ack(last_acked, packets.len()).await {
+                Ok(Reply::Ack(block_id)) if block_id == last_acked => {
+                    // Client lost some blocks of the window (RFC7440)
+                    trace!(
+                        "RRQ (peer: {}, block_id: {}) - Retransmit window",
+                        &self.peer,
+                        last_acked.wrapping_add(1)
+                    );
+                    continue;
+                }
+                Ok(Reply::Ack(block_id)) => {
                     trace!(
                         "RRQ (peer: {}, block_id: {}) - Received ACK",
                         &self.peer,
                         block_id
                     );
-                    return Ok(());
+
+                    // ACK of retransmitted packets can be of any of them
+                    if let (Some(rto), 0) = (&mut self.rto, retry) {
+                        rto.sample(sent_at.elapsed());
+                    }
+
+                    return Ok(block_id);
+                }
+                Ok(Reply::Error(e, msg)) => {
+                    trace!(
+                        "RRQ (peer: {}) - Received error {}: {}",
+                        &self.peer,
+                        e.code(),
+                        &msg
+                    );
+                    return Err(Error::PeerError(e, msg));
                 }
                 Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
                     trace!(
                         "RRQ (peer: {}, block_id: {}) - Timeout",
                         &self.peer,
-                        block_id
+                        last_acked.wrapping_add(1)
                     );
+                    self.events.emit(TransferEvent::TimedOut {
+                        block_id: last_acked.wrapping_add(1),
+                    });
+
+                    if let Some(rto) = &mut self.rto {
+                        rto.backoff();
+                    }
+
                     continue;
                 }
                 Err(e) => return Err(e.into()),
             }
         }
 
-        Err(Error::MaxSendRetriesReached(self.peer, block_id))
+        Err(Error::MaxSendRetriesReached(self.peer, last_acked.wrapping_add(1)))
     }
 
-    async fn recv_ack(&mut self, block_id: u16) -> io::Result<()> {
+    async fn recv_ack(
+        &mut self,
+        last_acked: u16,
+        window_len: usize,
+    ) -> io::Result<Reply> {
         // We can not use `self` within `async_std::io::timeout` because not all
         // struct members implement `Sync`. So we borrow only what we need.
         let socket = &mut self.socket;
+        let unknown_tid = &mut self.unknown_tid;
         let peer = self.peer;
+        let windowed = self.window_size > 1;
+        let timeout = match &self.rto {
+            Some(rto) => rto.timeout(),
+            None => self.timeout,
+        };
 
-        io_timeout(self.timeout, async {
+        io_timeout(timeout, async {
             let mut buf = [0u8; 1024];
 
             loop {
                 let (len, recved_peer) = socket.recv_from(&mut buf[..]).await?;
 
-                // if the packet do not come from the client we are serving, then ignore it
+                // if the packet do not come from the client we are serving,
+                // then reply with an error and ignore it
                 if recved_peer != peer {
+                    unknown_tid.reply(socket, &buf[..len], recved_peer).await;
                     continue;
                 }
 
-                // parse only valid Ack packets, the rest are ignored
-                if let Ok(Packet::Ack(recved_block_id)) =
-                    Packet::decode(&buf[..len])
-                {
-                    if recved_block_id == block_id {
-                        return Ok(());
+                // parse only valid Ack and Error packets, the rest are
+                // ignored
+                match Packet::decode(&buf[..len]) {
+                    Ok(Packet::Ack(recved_block_id)) => {
+                        let acked = recved_block_id.wrapping_sub(last_acked);
+
+                        // Accept ACK of any block within the window.
+                        if acked >= 1 && usize::from(acked) <= window_len {
+                            return Ok(Reply::Ack(recved_block_id));
+                        }
+
+                        // With windowsize, client acknowledges the last block
+                        // it received in order. Without it, duplicate ACKs are
+                        // ignored to avoid the Sorcerer's Apprentice Syndrome.
+                        if acked == 0 && windowed {
+                            return Ok(Reply::Ack(recved_block_id));
+                        }
+                    }
+                    // Client aborts the transfer, or does not accept our
+                    // OACK (RFC2347).
+                    Ok(Packet::Error(e)) => {
+                        let msg = parse_error_msg(&buf[..len]).unwrap_or("");
+                        return Ok(Reply::Error(e, msg.to_string()));
                     }
+                    // Ignore the rest
+                    _ => {}
                 }
             }
         })
-        .await?;
-
-        Ok(())
+        .await
     }
 
     async fn read_block(&mut self, buf: &mut [u8]) -> Result<usize> {
@@ -229,12 +403,18 @@ fn build_oack_opts(
 
     if !config.ignore_client_timeout {
         opts.timeout = req.opts.timeout;
+        opts.timeout_us = req.opts.timeout_us;
     }
 
     if let (Some(0), Some(file_size)) = (req.opts.transfer_size, file_size) {
         opts.transfer_size = Some(file_size);
     }
 
+    opts.window_size = req
+        .opts
+        .window_size
+        .map(|wsize| cmp::min(wsize, config.window_size_limit));
+
     if opts == Opts::default() {
         None
     } else {
@@ -0,0 +1,59 @@
+use std::cmp;
+use std::time::Duration;
+
+/// Retransmission timeout that adapts to the round-trip time of a client,
+/// like the one of TCP (RFC6298).
+pub(crate) struct Rto {
+    srtt: Option<Duration>,
+    rttvar: Duration,
+    rto: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl Rto {
+    /// Create an RTO that starts at `initial` and stays within `min` and
+    /// `max`.
+    pub(crate) fn new(initial: Duration, min: Duration, max: Duration) -> Self {
+        Rto {
+            srtt: None,
+            rttvar: Duration::ZERO,
+            rto: clamp(initial, min, max),
+            min,
+            max,
+        }
+    }
+
+    /// Current timeout.
+    pub(crate) fn timeout(&self) -> Duration {
+        self.rto
+    }
+
+    /// Update the timeout with a measured round-trip time.
+    ///
+    /// Round-trips of retransmitted packets are ambiguous and must not be
+    /// sampled (Karn's algorithm).
+    pub(crate) fn sample(&mut self, rtt: Duration) {
+        let (srtt, rttvar) = match self.srtt {
+            None => (rtt, rtt / 2),
+            Some(srtt) => {
+                let delta = srtt.abs_diff(rtt);
+                (srtt * 7 / 8 + rtt / 8, self.rttvar * 3 / 4 + delta / 4)
+            }
+        };
+
+        self.srtt = Some(srtt);
+        self.rttvar = rttvar;
+        self.rto = clamp(srtt + rttvar * 4, self.min, self.max);
+    }
+
+    /// Double the timeout after a loss.
+    pub(crate) fn backoff(&mut self) {
+        let rto = self.rto.checked_mul(2).unwrap_or(self.max);
+        self.rto = cmp::min(rto, self.max);
+    }
+}
+
+fn clamp(value: Duration, min: Duration, max: Duration) -> Duration {
+    cmp::max(cmp::min(value, max), min)
+}
@@ -1,177 +1,855 @@
+use async_channel::{Receiver, Sender};
 use async_executor::Executor;
-use async_io::Async;
+use async_io::{Async, Timer};
 use async_lock::Mutex;
+use bytes::Bytes;
+use futures_lite::{future, AsyncRead, AsyncWrite};
 use log::trace;
-use std::collections::HashSet;
+use std::collections::HashMap;
 use std::future::Future;
+use std::io;
+#[cfg(feature = "prometheus")]
+use std::net::TcpListener;
 use std::net::{IpAddr, SocketAddr, UdpSocket};
 use std::sync::Arc;
+use std::task::Poll;
 use std::time::Duration;
 
+use super::acl::Acl;
+use super::limits::{TransferLimits, TransferPermit};
+#[cfg(feature = "prometheus")]
+use super::metrics;
+use super::observer::{Events, Observer, TransferEvent};
+use super::pktinfo;
 use super::read_req::*;
+use super::socket::{PortRange, TransferSocket};
+use super::stats::{ServerStats, StatsCollector};
+use super::throttle::Throttle;
 use super::write_req::*;
-use super::Handler;
+use super::{Handler, HandlerRef, RequestContext, TransferOutcome as Outcome};
 use crate::error::*;
-use crate::packet::{Packet, RwReq};
+use crate::netascii::{NetasciiReader, NetasciiWriter};
+use crate::packet::{self, Mode, Packet, RwReq};
+use crate::utils::cancelled;
 
 /// TFTP server.
 pub struct TftpServer<H>
 where
     H: Handler,
 {
-    pub(crate) socket: Async<UdpSocket>,
-    pub(crate) handler: Arc<Mutex<H>>,
-    pub(crate) reqs_in_progress: Arc<Mutex<HashSet<SocketAddr>>>,
+    pub(crate) listeners: Vec<Listener>,
+    pub(crate) handler: HandlerRef<H>,
+    // In single port mode, packets of a peer are forwarded to its transfer
+    // through the sender.
+    pub(crate) reqs_in_progress:
+        Arc<Mutex<HashMap<SocketAddr, Option<Sender<Bytes>>>>>,
     pub(crate) ex: Executor<'static>,
     pub(crate) config: ServerConfig,
-    pub(crate) local_ip: IpAddr,
+    pub(crate) shutdown_tx: Sender<Duration>,
+    pub(crate) shutdown_rx: Receiver<Duration>,
+    // Closed when the in-progress requests must be cancelled.
+    pub(crate) cancel_tx: Sender<()>,
+    pub(crate) cancel_rx: Receiver<()>,
+    // Notified every time a request finishes.
+    pub(crate) req_done_tx: Sender<()>,
+    pub(crate) req_done_rx: Receiver<()>,
+    #[cfg(feature = "prometheus")]
+    pub(crate) metrics: Option<Async<TcpListener>>,
+}
+
+/// Handle of a [`TftpServer`], for controlling it while it serves.
+#[derive(Clone)]
+pub struct ServerHandle {
+    shutdown_tx: Sender<Duration>,
+    stats: Arc<StatsCollector>,
+}
+
+/// Listening socket of the server.
+pub(crate) struct Listener {
+    pub(crate) socket: Arc<Async<UdpSocket>>,
+    pub(crate) local_addr: SocketAddr,
+    // Destination addresses of packets are reported by `pktinfo`.
+    pub(crate) pktinfo: bool,
+}
+
+/// Packets that can be queued for a transfer of single port mode.
+const FORWARD_QUEUE_LEN: usize = 64;
+
+enum Event {
+    // Index of the listener, length, peer and local address of a packet.
+    Packet(usize, usize, SocketAddr, SocketAddr),
+    Shutdown(Duration),
 }
 
 #[derive(Clone)]
 pub(crate) struct ServerConfig {
     pub(crate) timeout: Duration,
+    // Bounds of the adaptive timeout of read requests.
+    pub(crate) adaptive_timeout: Option<(Duration, Duration)>,
     pub(crate) block_size_limit: Option<u16>,
+    pub(crate) min_block_size: Option<u16>,
+    pub(crate) window_size_limit: u16,
     pub(crate) max_send_retries: u32,
     pub(crate) ignore_client_timeout: bool,
     pub(crate) ignore_client_block_size: bool,
+    pub(crate) single_port: bool,
+    pub(crate) port_range: Option<PortRange>,
+    pub(crate) limits: TransferLimits,
+    pub(crate) throttle: Throttle,
+    pub(crate) rrq_acl: Acl,
+    pub(crate) wrq_acl: Acl,
+    pub(crate) drop_denied: bool,
+    pub(crate) unknown_tid_reply_limit: Option<u32>,
+    pub(crate) max_write_size: Option<u64>,
+    pub(crate) observer: Option<Arc<dyn Observer>>,
+    pub(crate) stats: Arc<StatsCollector>,
 }
 
-pub(crate) const DEFAULT_BLOCK_SIZE: usize = 512;
-
 impl<H: 'static> TftpServer<H>
 where
     H: Handler,
 {
     /// Returns the listenning socket address.
+    ///
+    /// If server listens on multiple addresses, this is the first one.
     pub fn listen_addr(&self) -> Result<SocketAddr> {
-        Ok(self.socket.get_ref().local_addr()?)
+        Ok(self.listeners[0].socket.get_ref().local_addr()?)
+    }
+
+    /// Returns all listening socket addresses.
+    pub fn listen_addrs(&self) -> Vec<SocketAddr> {
+        self.listeners.iter().map(|l| l.local_addr).collect()
+    }
+
+    /// Returns the address of the metrics HTTP server, if it is enabled.
+    #[cfg(feature = "prometheus")]
+    pub fn metrics_addr(&self) -> Option<SocketAddr> {
+        let listener = self.metrics.as_ref()?;
+        listener.get_ref().local_addr().ok()
+    }
+
+    /// Returns a handle that can shut down the server.
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            shutdown_tx: self.shutdown_tx.clone(),
+            stats: Arc::clone(&self.config.stats),
+        }
     }
 
     /// Consume and start the server.
+    ///
+    /// It returns after a shutdown that was requested via [`ServerHandle`].
     pub async fn serve(self) -> Result<()> {
         self.ex
             .run(async {
-                let mut buf = [0u8; 4096];
+                let requests = self.serve_requests();
 
-                loop {
-                    let (len, peer) = self.socket.recv_from(&mut buf).await?;
-                    self.handle_req_packet(peer, &buf[..len]).await;
-                }
+                // Metrics are served until the server stops
+                #[cfg(feature = "prometheus")]
+                let requests = future::or(requests, self.serve_metrics());
+
+                requests.await
             })
             .await
     }
 
-    async fn handle_req_packet(&self, peer: SocketAddr, data: &[u8]) {
+    async fn serve_requests(&self) -> Result<()> {
+        // Transfers of single port mode need to receive Data
+        // packets of any block size.
+        let mut buf = vec![0u8; 65536];
+        let mut next_listener = 0;
+
+        let grace = loop {
+            let recv = async {
+                let (i, len, peer, local_addr) =
+                    recv_from_any(&self.listeners, &mut buf, next_listener)
+                        .await?;
+                Ok::<_, Error>(Event::Packet(i, len, peer, local_addr))
+            };
+
+            let shutdown = async {
+                // Server keeps a sender, so this never fails
+                let grace = self.shutdown_rx.recv().await;
+                Ok(Event::Shutdown(grace.unwrap_or_default()))
+            };
+
+            match future::or(shutdown, recv).await? {
+                Event::Packet(i, len, peer, local_addr) => {
+                    // Do not let a busy listener starve the others
+                    next_listener = i + 1;
+                    let listener = &self.listeners[i];
+                    let data = &buf[..len];
+                    self.handle_packet(listener, local_addr, peer, data).await
+                }
+                Event::Shutdown(grace) => break grace,
+            }
+        };
+
+        self.shutdown(grace).await;
+        Ok(())
+    }
+
+    #[cfg(feature = "prometheus")]
+    async fn serve_metrics(&self) -> Result<()> {
+        match &self.metrics {
+            Some(listener) => {
+                let stats = &self.config.stats;
+                metrics::serve(listener, stats, &self.ex).await
+            }
+            None => future::pending().await,
+        }
+    }
+
+    async fn shutdown(&self, grace: Duration) {
+        trace!("Shutting down (grace period: {:?})", grace);
+
+        let stop_reqs = async {
+            let finished = future::or(
+                async {
+                    self.wait_reqs().await;
+                    true
+                },
+                async {
+                    Timer::after(grace).await;
+                    false
+                },
+            )
+            .await;
+
+            if !finished {
+                trace!("Cancelling requests in progress");
+                self.cancel_tx.close();
+                self.wait_reqs().await;
+            }
+        };
+
+        future::or(stop_reqs, self.forward_packets()).await;
+
+        trace!("Server stopped");
+    }
+
+    /// Keep forwarding packets to the transfers of single port mode until
+    /// they finish. New requests are dropped.
+    async fn forward_packets(&self) {
+        if !self.config.single_port {
+            return future::pending().await;
+        }
+
+        let mut buf = vec![0u8; 65536];
+        let mut next_listener = 0;
+
+        loop {
+            match recv_from_any(&self.listeners, &mut buf, next_listener).await
+            {
+                Ok((i, len, peer, _)) => {
+                    next_listener = i + 1;
+                    self.forward_packet(peer, &buf[..len]).await;
+                }
+                Err(e) => {
+                    trace!("Failed to receive packet: {}", e);
+                    return future::pending().await;
+                }
+            }
+        }
+    }
+
+    async fn wait_reqs(&self) {
+        while !self.reqs_in_progress.lock().await.is_empty() {
+            let _ = self.req_done_rx.recv().await;
+        }
+    }
+
+    async fn handle_packet(
+        &self,
+        listener: &Listener,
+        local_addr: SocketAddr,
+        peer: SocketAddr,
+        data: &[u8],
+    ) {
+        if self.forward_packet(peer, data).await {
+            return;
+        }
+
+        self.handle_req_packet(listener, local_addr, peer, data).await
+    }
+
+    /// Forward `data` to the transfer of `peer`, if it is in progress and
+    /// shares the listening socket.
+    async fn forward_packet(&self, peer: SocketAddr, data: &[u8]) -> bool {
+        match self.reqs_in_progress.lock().await.get(&peer) {
+            Some(Some(tx)) => {
+                // Like with UDP, packets are dropped if the transfer can
+                // not keep up.
+                let _ = tx.try_send(Bytes::copy_from_slice(data));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    async fn handle_req_packet(
+        &self,
+        listener: &Listener,
+        local_addr: SocketAddr,
+        peer: SocketAddr,
+        data: &[u8],
+    ) {
+        // Check access before we parse the request
+        let acl = match data.get(..2) {
+            Some([0, 1]) => &self.config.rrq_acl,
+            Some([0, 2]) => &self.config.wrq_acl,
+            // Ignore packets that are not requests
+            _ => return,
+        };
+
+        if !acl.is_allowed(peer.ip()) {
+            if self.config.drop_denied {
+                trace!("Request dropped (peer: {})", &peer);
+            } else {
+                let e = Error::Packet(packet::Error::PermissionDenied);
+                self.reply_error(listener, local_addr, e, peer);
+            }
+            return;
+        }
+
         let packet = match Packet::decode(data) {
             Ok(p @ Packet::Rrq(_)) => p,
             Ok(p @ Packet::Wrq(_)) => p,
+            // Reply to requests of unknown modes, instead of letting
+            // client to timeout
+            Err(e @ Error::UnknownMode(_)) => {
+                self.reply_error(listener, local_addr, e, peer);
+                return;
+            }
             // Ignore packets that are not requests
             Ok(_) => return,
             // Ignore invalid packets
             Err(_) => return,
         };
 
-        if !self.reqs_in_progress.lock().await.insert(peer) {
+        if let Packet::Rrq(req) | Packet::Wrq(req) = &packet {
+            // Mail mode is obsolete
+            if req.mode == Mode::Mail {
+                let e = Error::UnsupportedMode(req.mode);
+                self.reply_error(listener, local_addr, e, peer);
+                return;
+            }
+        }
+
+        if !self.opts_acceptable(&packet) {
+            let e = Error::Packet(packet::Error::OptionNegotiation);
+            self.reply_error(listener, local_addr, e, peer);
+            return;
+        }
+
+        let mut reqs_in_progress = self.reqs_in_progress.lock().await;
+
+        if reqs_in_progress.contains_key(&peer) {
             // Ignore pending requests
             return;
         }
 
+        let forwarded = if self.config.single_port {
+            let (tx, rx) = async_channel::bounded(FORWARD_QUEUE_LEN);
+            reqs_in_progress.insert(peer, Some(tx));
+            Some(rx)
+        } else {
+            reqs_in_progress.insert(peer, None);
+            None
+        };
+
+        drop(reqs_in_progress);
+
+        let transfer = self.transfer(listener, local_addr, peer, forwarded);
+
         match packet {
-            Packet::Rrq(req) => self.handle_rrq(peer, req),
-            Packet::Wrq(req) => self.handle_wrq(peer, req),
+            Packet::Rrq(req) => self.handle_rrq(transfer, local_addr, req),
+            Packet::Wrq(req) => self.handle_wrq(transfer, local_addr, req),
             _ => unreachable!(),
         }
     }
 
-    fn handle_rrq(&self, peer: SocketAddr, req: RwReq) {
+    /// Returns `false` if the options of a request are outside the limits of
+    /// the server.
+    fn opts_acceptable(&self, packet: &Packet) -> bool {
+        let (req, is_write) = match packet {
+            Packet::Rrq(req) => (req, false),
+            Packet::Wrq(req) => (req, true),
+            _ => return true,
+        };
+
+        // Block size is not negotiated if server ignores it
+        if !self.config.ignore_client_block_size {
+            if let (Some(bsize), Some(min)) =
+                (req.opts.block_size, self.config.min_block_size)
+            {
+                if bsize < min {
+                    return false;
+                }
+            }
+        }
+
+        if is_write {
+            if let (Some(size), Some(max)) =
+                (req.opts.transfer_size, self.config.max_write_size)
+            {
+                if size > max {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    fn reply_error(
+        &self,
+        listener: &Listener,
+        local_addr: SocketAddr,
+        error: Error,
+        peer: SocketAddr,
+    ) {
+        trace!("Request rejected (peer: {}, error: {})", &peer, &error);
+        self.config.stats.error(&packet::Error::from(&error));
+
+        let local_ip = local_addr.ip();
+        let listener = Arc::clone(&listener.socket);
+        let shared = self.config.single_port;
+        let ports = self.config.port_range.clone();
+
+        self.ex
+            .spawn(async move {
+                let sent = send_error(
+                    &error,
+                    peer,
+                    &listener,
+                    shared,
+                    local_ip,
+                    ports.as_ref(),
+                )
+                .await;
+
+                if let Err(e) = sent {
+                    trace!("Failed to send error to peer {}: {}", &peer, &e);
+                }
+            })
+            .detach();
+    }
+
+    fn run_req(
+        &self,
+        req_fut: impl Future<Output = ()>,
+        peer: SocketAddr,
+    ) -> impl Future<Output = ()> {
+        let reqs_in_progress = Arc::clone(&self.reqs_in_progress);
+        let req_done_tx = self.req_done_tx.clone();
+
+        async move {
+            req_fut.await;
+
+            reqs_in_progress.lock().await.remove(&peer);
+            let _ = req_done_tx.try_send(());
+        }
+    }
+
+    fn transfer(
+        &self,
+        listener: &Listener,
+        local_addr: SocketAddr,
+        peer: SocketAddr,
+        forwarded: Option<Receiver<Bytes>>,
+    ) -> Transfer {
+        Transfer {
+            peer,
+            config: self.config.clone(),
+            // Reply from the address that the request arrived on
+            local_ip: local_addr.ip(),
+            listener: Arc::clone(&listener.socket),
+            forwarded,
+            cancel: self.cancel_rx.clone(),
+        }
+    }
+
+    fn handle_rrq(
+        &self,
+        transfer: Transfer,
+        local_addr: SocketAddr,
+        req: RwReq,
+    ) {
+        let peer = transfer.peer;
         trace!("RRQ recieved (peer: {}, req: {:?})", &peer, &req);
 
+        let ctx = Arc::new(RequestContext::new(peer, local_addr, &req));
+        let events = Events::new(
+            self.config.observer.clone(),
+            Arc::clone(&self.config.stats),
+            Arc::clone(&ctx),
+        );
         let handler = Arc::clone(&self.handler);
-        let config = self.config.clone();
-        let local_ip = self.local_ip.clone();
+
+        events.emit(TransferEvent::Requested);
 
         // Prepare request future
         let req_fut = async move {
-            let (mut reader, size) = handler
-                .lock()
-                .await
-                .read_req_open(&peer, req.filename.as_ref())
-                .await
-                .map_err(Error::Packet)?;
-
-            let mut read_req =
-                ReadRequest::init(&mut reader, size, peer, &req, config, local_ip)
-                    .await?;
-
-            read_req.handle().await;
+            let _permit = match transfer.permit().await {
+                Ok(permit) => permit,
+                Err(e) => {
+                    transfer.failed(e, &events).await;
+                    return;
+                }
+            };
+
+            let open = async {
+                handler
+                    .read_req_open(&ctx, ctx.path())
+                    .await
+                    .map_err(Error::Packet)
+            };
+
+            let (reader, size) =
+                match future::or(open, cancelled(&transfer.cancel)).await {
+                    Ok(opened) => opened,
+                    Err(e) => {
+                        transfer.failed(e, &events).await;
+                        return;
+                    }
+                };
+
+            let outcome = match ctx.mode() {
+                Mode::Netascii => {
+                    // Size of netascii data is unknown without encoding the
+                    // whole file, so we can not reply with `tsize`.
+                    let reader = NetasciiReader::new(reader);
+                    transfer.serve_rrq(reader, None, &req, &events).await
+                }
+                _ => transfer.serve_rrq(reader, size, &req, &events).await,
+            };
 
-            Ok(())
+            handler.read_req_finished(&ctx, &outcome).await;
         };
 
-        let reqs_in_progress = Arc::clone(&self.reqs_in_progress);
-
         // Run request future in a new task
-        self.ex.spawn(run_req(req_fut, peer, reqs_in_progress, local_ip)).detach();
+        self.ex.spawn(self.run_req(req_fut, peer)).detach();
     }
 
-    fn handle_wrq(&self, peer: SocketAddr, req: RwReq) {
+    fn handle_wrq(
+        &self,
+        transfer: Transfer,
+        local_addr: SocketAddr,
+        req: RwReq,
+    ) {
+        let peer = transfer.peer;
         trace!("WRQ recieved (peer: {}, req: {:?})", &peer, &req);
 
+        let ctx = Arc::new(RequestContext::new(peer, local_addr, &req));
+        let events = Events::new(
+            self.config.observer.clone(),
+            Arc::clone(&self.config.stats),
+            Arc::clone(&ctx),
+        );
         let handler = Arc::clone(&self.handler);
-        let config = self.config.clone();
-        let local_ip = self.local_ip.clone();
+
+        events.emit(TransferEvent::Requested);
 
         // Prepare request future
         let req_fut = async move {
-            let mut writer = handler
-                .lock()
-                .await
-                .write_req_open(
-                    &peer,
-                    req.filename.as_ref(),
-                    req.opts.transfer_size,
-                )
-                .await
-                .map_err(Error::Packet)?;
+            let _permit = match transfer.permit().await {
+                Ok(permit) => permit,
+                Err(e) => {
+                    transfer.failed(e, &events).await;
+                    return;
+                }
+            };
+
+            // `tsize` of netascii data is not the size of the decoded file.
+            let size = match ctx.mode() {
+                Mode::Netascii => None,
+                _ => ctx.opts().transfer_size,
+            };
+
+            let open = async {
+                handler
+                    .write_req_open(&ctx, ctx.path(), size)
+                    .await
+                    .map_err(Error::Packet)
+            };
+
+            let writer =
+                match future::or(open, cancelled(&transfer.cancel)).await {
+                    Ok(writer) => writer,
+                    Err(e) => {
+                        transfer.failed(e, &events).await;
+                        return;
+                    }
+                };
+
+            let outcome = match ctx.mode() {
+                Mode::Netascii => {
+                    let writer = NetasciiWriter::new(writer);
+                    transfer.serve_wrq(writer, &req, &events).await
+                }
+                _ => transfer.serve_wrq(writer, &req, &events).await,
+            };
+
+            handler.write_req_finished(&ctx, &outcome).await;
+        };
 
-            let mut write_req =
-                WriteRequest::init(&mut writer, peer, &req, config, local_ip).await?;
+        // Run request future in a new task
+        self.ex.spawn(self.run_req(req_fut, peer)).detach();
+    }
+}
+
+/// Everything a transfer needs, besides its request.
+struct Transfer {
+    peer: SocketAddr,
+    config: ServerConfig,
+    local_ip: IpAddr,
+    // Listening socket that received the request.
+    listener: Arc<Async<UdpSocket>>,
+    // Packets of the peer that server forwards, in single port mode.
+    forwarded: Option<Receiver<Bytes>>,
+    cancel: Receiver<()>,
+}
+
+impl Transfer {
+    /// Wait until the transfer limits allow it to start.
+    async fn permit(&self) -> Result<TransferPermit> {
+        let acquire = self.config.limits.acquire(self.peer.ip());
+        future::or(acquire, cancelled(&self.cancel)).await
+    }
 
-            write_req.handle().await;
+    fn socket(&self) -> Result<TransferSocket> {
+        match &self.forwarded {
+            Some(rx) => Ok(TransferSocket::Shared {
+                socket: Arc::clone(&self.listener),
+                peer: self.peer,
+                local_ip: self.local_ip,
+                rx: rx.clone(),
+            }),
+            None => TransferSocket::bind(
+                self.local_ip,
+                self.config.port_range.as_ref(),
+            ),
+        }
+    }
 
-            Ok(())
+    async fn serve_rrq<R>(
+        &self,
+        mut reader: R,
+        size: Option<u64>,
+        req: &RwReq,
+        events: &Events,
+    ) -> Outcome
+    where
+        R: AsyncRead + Send + Unpin,
+    {
+        let socket = match self.socket() {
+            Ok(socket) => socket,
+            Err(e) => return self.failed(e, events).await,
         };
 
-        let reqs_in_progress = Arc::clone(&self.reqs_in_progress);
+        let read_req = ReadRequest::init(
+            &mut reader,
+            size,
+            self.peer,
+            req,
+            self.config.clone(),
+            socket,
+            events.clone(),
+        )
+        .await;
+
+        match read_req {
+            Ok(mut read_req) => {
+                let outcome = read_req.handle(self.cancel.clone()).await;
+                events.finished(&outcome);
+                outcome
+            }
+            Err(e) => self.failed(e, events).await,
+        }
+    }
 
-        // Run request future in a new task
-        self.ex.spawn(run_req(req_fut, peer, reqs_in_progress, local_ip)).detach();
+    async fn serve_wrq<W>(
+        &self,
+        mut writer: W,
+        req: &RwReq,
+        events: &Events,
+    ) -> Outcome
+    where
+        W: AsyncWrite + Send + Unpin,
+    {
+        let socket = match self.socket() {
+            Ok(socket) => socket,
+            Err(e) => return self.failed(e, events).await,
+        };
+
+        let write_req = WriteRequest::init(
+            &mut writer,
+            self.peer,
+            req,
+            self.config.clone(),
+            socket,
+            events.clone(),
+        )
+        .await;
+
+        match write_req {
+            Ok(mut write_req) => {
+                let outcome = write_req.handle(self.cancel.clone()).await;
+                events.finished(&outcome);
+                outcome
+            }
+            Err(e) => self.failed(e, events).await,
+        }
+    }
+
+    /// Transfer failed before it started.
+    async fn failed(&self, error: Error, events: &Events) -> Outcome {
+        trace!("Request failed (peer: {}, error: {}", &self.peer, &error);
+
+        let sent = send_error(
+            &error,
+            self.peer,
+            &self.listener,
+            self.forwarded.is_some(),
+            self.local_ip,
+            self.config.port_range.as_ref(),
+        )
+        .await;
+
+        if let Err(e) = sent {
+            trace!("Failed to send error to peer {}: {}", &self.peer, &e);
+        }
+
+        let outcome = Outcome::new(0, Err(error));
+        events.finished(&outcome);
+        outcome
     }
 }
 
-async fn send_error(error: Error, peer: SocketAddr, local_ip: IpAddr) -> Result<()> {
-    let addr: SocketAddr = SocketAddr::new(local_ip, 0);
-    let socket = Async::<UdpSocket>::bind(addr).map_err(Error::Bind)?;
+/// Receive a packet from any of `listeners`, checking them in turn from
+/// `first`.
+///
+/// Returns the index of the listener, the length, the peer and the local
+/// address of the packet.
+async fn recv_from_any(
+    listeners: &[Listener],
+    buf: &mut [u8],
+    first: usize,
+) -> io::Result<(usize, usize, SocketAddr, SocketAddr)> {
+    future::poll_fn(|cx| {
+        for n in 0..listeners.len() {
+            let i = (first + n) % listeners.len();
+            let listener = &listeners[i];
+            let socket = &listener.socket;
+
+            loop {
+                let recved = if listener.pktinfo {
+                    pktinfo::recv_from(socket.get_ref(), buf)
+                } else {
+                    socket.get_ref().recv_from(buf).map(|(l, p)| (l, p, None))
+                };
+
+                match recved {
+                    Ok((len, peer, dest)) => {
+                        let listen_addr = listener.local_addr;
+                        let ip =
+                            pktinfo::transfer_local_ip(listen_addr.ip(), dest);
+                        let local_addr =
+                            SocketAddr::new(ip, listen_addr.port());
+                        return Poll::Ready(Ok((i, len, peer, local_addr)));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) if is_datagram_error(&e) => {
+                        trace!("Failed to receive packet: {}", e);
+                        continue;
+                    }
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
 
-    let data = Packet::Error(error.into()).to_bytes();
-    socket.send_to(&data[..], peer).await?;
+                // Register for wakeup, or retry if it became readable
+                match socket.poll_readable(cx) {
+                    Poll::Ready(Ok(())) => continue,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => break,
+                }
+            }
+        }
 
-    Ok(())
+        Poll::Pending
+    })
+    .await
 }
 
-async fn run_req(
-    req_fut: impl Future<Output = Result<()>>,
+/// Returns `true` if `e` concerns a single datagram, so the socket can keep
+/// receiving.
+///
+/// For example, Windows reports an ICMP port unreachable of a previously
+/// sent packet as `ConnectionReset` on the next receive.
+fn is_datagram_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::Interrupted
+    )
+}
+
+/// Send `error` from `listener` if transfers share it, otherwise from a new
+/// socket.
+///
+/// With a port range, the new socket is bound on one of its ports, so the
+/// error passes the same firewall rules as transfers. If all of them are
+/// in use, `listener` is used instead.
+async fn send_error(
+    error: &Error,
     peer: SocketAddr,
-    reqs_in_progress: Arc<Mutex<HashSet<SocketAddr>>>,
+    listener: &Async<UdpSocket>,
+    shared: bool,
     local_ip: IpAddr,
-) {
-    if let Err(e) = req_fut.await {
-        trace!("Request failed (peer: {}, error: {}", &peer, &e);
-
-        if let Err(e) = send_error(e, peer, local_ip).await {
-            trace!("Failed to send error to peer {}: {}", &peer, &e);
+    ports: Option<&PortRange>,
+) -> Result<()> {
+    let bound;
+    let socket = match (shared, ports) {
+        (true, _) => listener,
+        (false, Some(ports)) => match ports.bind(local_ip) {
+            Ok(socket) => {
+                bound = socket;
+                &bound
+            }
+            Err(Error::PortRangeExhausted(_)) => listener,
+            Err(e) => return Err(e),
+        },
+        (false, None) => {
+            let addr = SocketAddr::new(local_ip, 0);
+            bound = Async::<UdpSocket>::bind(addr).map_err(Error::Bind)?;
+            &bound
         }
+    };
+
+    let data = packet::encode_error_reply(error);
+    pktinfo::send_from(socket, &data[..], peer, local_ip).await?;
+
+    Ok(())
+}
+
+impl ServerHandle {
+    /// Shut down the server.
+    ///
+    /// Server stops accepting new requests and waits up to `grace` for the
+    /// requests in progress to finish. The remaining ones are cancelled
+    /// and their peers receive an error. After that [`TftpServer::serve`]
+    /// returns.
+    ///
+    /// The `*_finished` hooks of [`Handler`] are not bounded by `grace`,
+    /// `serve` returns only after the running ones complete.
+    pub fn shutdown(&self, grace: Duration) {
+        // Only the first shutdown matters
+        let _ = self.shutdown_tx.try_send(grace);
     }
 
-    reqs_in_progress.lock().await.remove(&peer);
+    /// Statistics of the server since it was built.
+    pub fn stats(&self) -> ServerStats {
+        self.stats.snapshot()
+    }
 }
@@ -0,0 +1,194 @@
+use async_channel::Receiver;
+use async_io::Async;
+use bytes::Bytes;
+use log::trace;
+use std::cmp;
+use std::io;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::pktinfo;
+use crate::error::{Error, Result};
+use crate::packet::{self, Packet};
+
+/// Socket that a transfer uses to talk with its peer.
+pub(crate) enum TransferSocket {
+    /// Socket with its own port, which is the transfer ID of the server.
+    Dedicated(Async<UdpSocket>),
+    /// Listening socket of the server. It receives the packets of all
+    /// peers, so server forwards the ones of `peer` over `rx`.
+    Shared {
+        socket: Arc<Async<UdpSocket>>,
+        peer: SocketAddr,
+        // Source address of the packets we send.
+        local_ip: IpAddr,
+        rx: Receiver<Bytes>,
+    },
+}
+
+/// Ports that transfer sockets are bound to.
+#[derive(Clone)]
+pub(crate) struct PortRange {
+    ports: RangeInclusive<u16>,
+    // Offset of the port that is tried first by the next transfer.
+    next: Arc<AtomicUsize>,
+}
+
+impl PortRange {
+    pub(crate) fn new(ports: RangeInclusive<u16>) -> Self {
+        PortRange {
+            ports,
+            next: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Bind a socket on the first free port, starting after the port of
+    /// the previous transfer.
+    pub(crate) fn bind(&self, local_ip: IpAddr) -> Result<Async<UdpSocket>> {
+        let first = usize::from(*self.ports.start());
+        let len = self.ports.clone().count();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        for i in 0..len {
+            let port = (first + (start + i) % len) as u16;
+            let addr = SocketAddr::new(local_ip, port);
+
+            match Async::<UdpSocket>::bind(addr) {
+                Ok(socket) => {
+                    self.next.store(start + i + 1, Ordering::Relaxed);
+                    return Ok(socket);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AddrInUse => continue,
+                Err(e) => return Err(Error::Bind(e)),
+            }
+        }
+
+        Err(Error::PortRangeExhausted(self.ports.clone()))
+    }
+}
+
+impl TransferSocket {
+    /// Bind a dedicated socket on a port of `ports`, or on a random port.
+    pub(crate) fn bind(
+        local_ip: IpAddr,
+        ports: Option<&PortRange>,
+    ) -> Result<TransferSocket> {
+        let socket = match ports {
+            Some(ports) => ports.bind(local_ip)?,
+            None => {
+                let addr = SocketAddr::new(local_ip, 0);
+                Async::<UdpSocket>::bind(addr).map_err(Error::Bind)?
+            }
+        };
+
+        Ok(TransferSocket::Dedicated(socket))
+    }
+
+    pub(crate) async fn send_to(
+        &self,
+        buf: &[u8],
+        peer: SocketAddr,
+    ) -> io::Result<usize> {
+        match self {
+            TransferSocket::Dedicated(socket) => {
+                socket.send_to(buf, peer).await
+            }
+            TransferSocket::Shared {
+                socket,
+                local_ip,
+                ..
+            } => pktinfo::send_from(socket, buf, peer, *local_ip).await,
+        }
+    }
+
+    pub(crate) async fn recv_from(
+        &self,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr)> {
+        match self {
+            TransferSocket::Dedicated(socket) => socket.recv_from(buf).await,
+            TransferSocket::Shared {
+                peer,
+                rx,
+                ..
+            } => {
+                // Server stopped forwarding packets
+                let data = rx
+                    .recv()
+                    .await
+                    .map_err(|_| io::ErrorKind::ConnectionAborted)?;
+
+                // Truncate like a UDP socket does
+                let len = cmp::min(data.len(), buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+
+                Ok((len, *peer))
+            }
+        }
+    }
+}
+
+/// Replies of a transfer to the packets that arrive from other ports than
+/// its peer (RFC1350).
+pub(crate) struct UnknownTidReplies {
+    // Maximum replies per second
+    limit: Option<u32>,
+    window_start: Instant,
+    sent: u32,
+}
+
+impl UnknownTidReplies {
+    pub(crate) fn new(limit: Option<u32>) -> Self {
+        UnknownTidReplies {
+            limit,
+            window_start: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// Reply with an Unknown transfer ID error to `data` that was received
+    /// from `peer`.
+    ///
+    /// Errors are never replied, so two transfers can not bounce errors to
+    /// each other.
+    pub(crate) async fn reply(
+        &mut self,
+        socket: &TransferSocket,
+        data: &[u8],
+        peer: SocketAddr,
+    ) {
+        if data.starts_with(&[0, 5]) || !self.allow() {
+            return;
+        }
+
+        trace!("Unknown transfer ID (peer: {})", peer);
+
+        let error = Packet::Error(packet::Error::UnknownTransferId);
+        // We do not care if `send_to` resulted to an IO error.
+        let _ = socket.send_to(&error.to_bytes()[..], peer).await;
+    }
+
+    fn allow(&mut self) -> bool {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+
+        let now = Instant::now();
+
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.sent = 0;
+        }
+
+        if self.sent < limit {
+            self.sent += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
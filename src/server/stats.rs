@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::packet;
+
+/// Upper bounds of the buckets of [`DurationHistogram`].
+const DURATION_BUCKETS: [Duration; 8] = [
+    Duration::from_millis(100),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+    Duration::from_secs(30),
+    Duration::from_secs(60),
+    Duration::from_secs(300),
+];
+
+/// Codes of `packet::Error` that are counted separately.
+const ERROR_CODES: usize = 9;
+
+/// Counters of the server, updated by the transfers.
+#[derive(Default)]
+pub(crate) struct StatsCollector {
+    active_transfers: AtomicU64,
+    completed_transfers: AtomicU64,
+    failed_transfers: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    retransmissions: AtomicU64,
+    timeouts: AtomicU64,
+    errors: [AtomicU64; ERROR_CODES],
+    // Transfers per bucket of `DURATION_BUCKETS`, and the ones that took
+    // longer.
+    durations: [AtomicU64; DURATION_BUCKETS.len() + 1],
+    durations_sum_us: AtomicU64,
+}
+
+impl StatsCollector {
+    pub(crate) fn transfer_started(&self) {
+        self.active_transfers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn transfer_finished(&self, success: bool, duration: Duration) {
+        self.active_transfers.fetch_sub(1, Ordering::Relaxed);
+
+        if success {
+            self.completed_transfers.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.failed_transfers.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let bucket = DURATION_BUCKETS
+            .iter()
+            .position(|bound| duration <= *bound)
+            .unwrap_or(DURATION_BUCKETS.len());
+
+        self.durations[bucket].fetch_add(1, Ordering::Relaxed);
+        self.durations_sum_us
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn bytes_received(&self, bytes: usize) {
+        self.bytes_received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn retransmission(&self) {
+        self.retransmissions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count an error that was sent to a client.
+    pub(crate) fn error(&self, error: &packet::Error) {
+        let code = usize::from(error.code());
+
+        if let Some(counter) = self.errors.get(code) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> ServerStats {
+        let errors = self
+            .errors
+            .iter()
+            .enumerate()
+            .map(|(code, count)| (code as u16, count.load(Ordering::Relaxed)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+
+        let mut total = 0;
+        let mut buckets = Vec::with_capacity(DURATION_BUCKETS.len());
+
+        for (bound, count) in DURATION_BUCKETS.iter().zip(&self.durations) {
+            total += count.load(Ordering::Relaxed);
+            buckets.push((*bound, total));
+        }
+
+        let longer =
+            self.durations[DURATION_BUCKETS.len()].load(Ordering::Relaxed);
+        let sum_us = self.durations_sum_us.load(Ordering::Relaxed);
+
+        ServerStats {
+            active_transfers: self.active_transfers.load(Ordering::Relaxed),
+            completed_transfers: self
+                .completed_transfers
+                .load(Ordering::Relaxed),
+            failed_transfers: self.failed_transfers.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            retransmissions: self.retransmissions.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            errors,
+            transfer_durations: DurationHistogram {
+                buckets,
+                count: total + longer,
+                sum: Duration::from_micros(sum_us),
+            },
+        }
+    }
+}
+
+/// Snapshot of the statistics of a server.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ServerStats {
+    /// Transfers in progress, including the ones that wait for a
+    /// transfer limit.
+    pub active_transfers: u64,
+    /// Transfers that finished successfully.
+    pub completed_transfers: u64,
+    /// Transfers that failed.
+    pub failed_transfers: u64,
+    /// Data bytes sent for read requests, including retransmissions.
+    pub bytes_sent: u64,
+    /// Data bytes received for write requests.
+    pub bytes_received: u64,
+    /// Packets that were sent again, after a timeout or a lost block.
+    pub retransmissions: u64,
+    /// Times that a client did not reply in time.
+    pub timeouts: u64,
+    /// Errors that were sent to clients, by their code of RFC1350.
+    pub errors: BTreeMap<u16, u64>,
+    /// Durations of the finished transfers.
+    pub transfer_durations: DurationHistogram,
+}
+
+/// Histogram of durations.
+#[derive(Debug, Clone)]
+pub struct DurationHistogram {
+    buckets: Vec<(Duration, u64)>,
+    count: u64,
+    sum: Duration,
+}
+
+impl DurationHistogram {
+    /// Upper bounds of the buckets, with the number of samples that are
+    /// less or equal to them.
+    pub fn buckets(&self) -> &[(Duration, u64)] {
+        &self.buckets
+    }
+
+    /// Number of all samples.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of all samples.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+}
@@ -0,0 +1,130 @@
+use async_io::Timer;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+/// Token bucket that limits a rate of bytes per second.
+///
+/// Bucket holds up to one second of tokens. Bytes are taken even if there are
+/// not enough tokens, and the caller waits until the debt is paid off. This
+/// way packets that are bigger than the bucket are also allowed.
+pub(crate) struct TokenBucket {
+    rate: u64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: u64) -> Self {
+        TokenBucket {
+            rate,
+            state: Mutex::new(BucketState {
+                tokens: rate as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `bytes` tokens and wait until the bucket allows them.
+    pub(crate) async fn take(&self, bytes: usize) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let rate = self.rate as f64;
+
+            let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+            state.tokens = (state.tokens + elapsed * rate).min(rate);
+            state.last_refill = now;
+            state.tokens -= bytes as f64;
+
+            if state.tokens < 0.0 {
+                Some(Duration::from_secs_f64(-state.tokens / rate))
+            } else {
+                None
+            }
+        };
+
+        if let Some(wait) = wait {
+            Timer::after(wait).await;
+        }
+    }
+}
+
+/// Bandwidth limits of the server.
+#[derive(Clone, Default)]
+pub(crate) struct Throttle {
+    per_transfer: Option<u64>,
+    per_ip: Option<u64>,
+    total: Option<Arc<TokenBucket>>,
+    // Buckets of the clients that have transfers in progress.
+    clients: Arc<Mutex<HashMap<IpAddr, Weak<TokenBucket>>>>,
+}
+
+/// Buckets that limit a single transfer.
+pub(crate) struct TransferThrottle {
+    buckets: Vec<Arc<TokenBucket>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(
+        per_transfer: Option<u64>,
+        per_ip: Option<u64>,
+        total: Option<u64>,
+    ) -> Self {
+        Throttle {
+            per_transfer,
+            per_ip,
+            total: total.map(|rate| Arc::new(TokenBucket::new(rate))),
+            clients: Arc::default(),
+        }
+    }
+
+    /// Buckets of a new transfer of client `ip`.
+    pub(crate) fn transfer(&self, ip: IpAddr) -> TransferThrottle {
+        let mut buckets = Vec::new();
+
+        if let Some(rate) = self.per_transfer {
+            buckets.push(Arc::new(TokenBucket::new(rate)));
+        }
+
+        if let Some(rate) = self.per_ip {
+            let mut clients = self.clients.lock().unwrap();
+
+            // Forget clients without transfers
+            clients.retain(|_, bucket| bucket.strong_count() > 0);
+
+            let bucket = match clients.get(&ip).and_then(Weak::upgrade) {
+                Some(bucket) => bucket,
+                None => {
+                    let bucket = Arc::new(TokenBucket::new(rate));
+                    clients.insert(ip, Arc::downgrade(&bucket));
+                    bucket
+                }
+            };
+
+            buckets.push(bucket);
+        }
+
+        if let Some(bucket) = &self.total {
+            buckets.push(Arc::clone(bucket));
+        }
+
+        TransferThrottle {
+            buckets,
+        }
+    }
+}
+
+impl TransferThrottle {
+    /// Wait until all limits allow `bytes` to be transferred.
+    pub(crate) async fn consume(&self, bytes: usize) {
+        for bucket in &self.buckets {
+            bucket.take(bytes).await;
+        }
+    }
+}
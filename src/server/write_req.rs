@@ -1,23 +1,38 @@
-use async_io::Async;
+use async_channel::Receiver;
 use bytes::{Buf, Bytes, BytesMut};
-use futures_lite::{AsyncWrite, AsyncWriteExt};
+use futures_lite::{future, AsyncWrite, AsyncWriteExt};
 use log::trace;
 use std::cmp;
 use std::io;
-use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::net::SocketAddr;
 use std::time::Duration;
 
+use super::observer::{Events, TransferEvent};
+use super::socket::{TransferSocket, UnknownTidReplies};
+use super::throttle::TransferThrottle;
 use crate::error::{Error, Result};
-use crate::packet::{Opts, Packet, RwReq, PACKET_DATA_HEADER_LEN};
-use crate::server::{ServerConfig, DEFAULT_BLOCK_SIZE};
-use crate::utils::io_timeout;
+use crate::packet::{
+    self, Opts, Packet, RwReq, DEFAULT_BLOCK_SIZE, PACKET_DATA_HEADER_LEN,
+};
+use crate::parse::parse_error_msg;
+use crate::server::{ServerConfig, TransferOutcome};
+use crate::utils::{cancelled, io_timeout};
+
+enum Reply {
+    Data(Bytes),
+    Gap,
+    Error(packet::Error, String),
+}
 
 pub(crate) struct WriteRequest<'w, W>
 where
     W: AsyncWrite + Send,
 {
     peer: SocketAddr,
-    socket: Async<UdpSocket>,
+    socket: TransferSocket,
+    unknown_tid: UnknownTidReplies,
+    throttle: TransferThrottle,
+    events: Events,
     writer: &'w mut W,
     // BytesMut reclaims memory only if it is continuous.
     // Because we always need to keep the previous ACK, we can not use
@@ -26,9 +41,14 @@ where
     buffer: BytesMut,
     ack: BytesMut,
     block_size: usize,
+    window_size: usize,
+    // Blocks received since the last ACK.
+    unacked_blocks: usize,
     timeout: Duration,
     max_retries: u32,
     oack_opts: Option<Opts>,
+    // Data bytes written to `writer`.
+    bytes: u64,
 }
 
 impl<'w, W> WriteRequest<'w, W>
@@ -40,7 +60,8 @@ where
         peer: SocketAddr,
         req: &RwReq,
         config: ServerConfig,
-        local_ip: IpAddr,
+        socket: TransferSocket,
+        events: Events,
     ) -> Result<WriteRequest<'w, W>> {
         let oack_opts = build_oack_opts(&config, req);
 
@@ -50,38 +71,56 @@ where
             .map(usize::from)
             .unwrap_or(DEFAULT_BLOCK_SIZE);
 
+        let window_size = oack_opts
+            .as_ref()
+            .and_then(|o| o.window_size)
+            .map(usize::from)
+            .unwrap_or(1);
+
         let timeout = oack_opts
             .as_ref()
-            .and_then(|o| o.timeout)
-            .map(|t| Duration::from_secs(u64::from(t)))
+            .and_then(Opts::timeout_duration)
             .unwrap_or(config.timeout);
 
-        let addr = SocketAddr::new(local_ip, 0);
-        let socket = Async::<UdpSocket>::bind(addr).map_err(Error::Bind)?;
-
         Ok(WriteRequest {
             peer,
             socket,
+            unknown_tid: UnknownTidReplies::new(config.unknown_tid_reply_limit),
+            throttle: config.throttle.transfer(peer.ip()),
+            events,
             writer,
             buffer: BytesMut::new(),
             ack: BytesMut::new(),
             block_size,
+            window_size,
+            unacked_blocks: 0,
             timeout,
             max_retries: config.max_send_retries,
             oack_opts,
+            bytes: 0,
         })
     }
 
-    pub(crate) async fn handle(&mut self) {
-        if let Err(e) = self.try_handle().await {
-            trace!("WRQ request failed (peer: {}, error: {}", self.peer, &e);
+    pub(crate) async fn handle(
+        &mut self,
+        cancel: Receiver<()>,
+    ) -> TransferOutcome {
+        let result = future::or(self.try_handle(), cancelled(&cancel)).await;
 
-            Packet::Error(e.into()).encode(&mut self.buffer);
-            let buf = self.buffer.split().freeze();
-            // Errors are never retransmitted.
-            // We do not care if `send_to` resulted to an IO error.
-            let _ = self.socket.send_to(&buf[..], self.peer).await;
+        if let Err(ref e) = result {
+            trace!("WRQ request failed (peer: {}, error: {}", self.peer, e);
+
+            // Errors of the client are never replied.
+            if !matches!(e, Error::PeerError(..)) {
+                Packet::Error(e.into()).encode(&mut self.buffer);
+                let buf = self.buffer.split().freeze();
+                // Errors are never retransmitted.
+                // We do not care if `send_to` resulted to an IO error.
+                let _ = self.socket.send_to(&buf[..], self.peer).await;
+            }
         }
+
+        TransferOutcome::new(self.bytes, result)
     }
 
     async fn try_handle(&mut self) -> Result<()> {
@@ -89,7 +128,10 @@ where
 
         // Send first Ack/OAck
         match self.oack_opts.take() {
-            Some(opts) => Packet::OAck(opts).encode(&mut self.ack),
+            Some(opts) => {
+                self.events.emit(TransferEvent::Negotiated(&opts));
+                Packet::OAck(opts).encode(&mut self.ack);
+            }
             None => Packet::Ack(0).encode(&mut self.ack),
         }
 
@@ -100,31 +142,76 @@ where
             block_id = block_id.wrapping_add(1);
             let data = self.recv_data(block_id).await?;
 
+            self.events.emit(TransferEvent::BlockReceived {
+                block_id,
+                offset: self.bytes,
+                len: data.len(),
+            });
+
+            // Client waits for our ACK, so delaying it slows down the upload
+            self.throttle.consume(data.len()).await;
+
             // Write data to file
             self.writer.write_all(&data[..]).await?;
+            self.bytes += data.len() as u64;
 
-            if data.len() < self.block_size {
+            let is_last_block = data.len() < self.block_size;
+
+            // Acknowledge only the last block of each window (RFC7440)
+            self.unacked_blocks += 1;
+
+            if is_last_block {
                 break;
             }
+
+            if self.unacked_blocks == self.window_size {
+                self.send_ack(block_id).await?;
+            }
         }
 
+        // Last block is acknowledged only after all data are stored, so
+        // client gets an error if closing fails.
+        self.writer.close().await?;
+        self.send_ack(block_id).await?;
+
         Ok(())
     }
 
     async fn recv_data(&mut self, block_id: u16) -> Result<Bytes> {
         for _ in 0..=self.max_retries {
             match self.recv_data_block(block_id).await {
-                Ok(data) => {
-                    // Data received, send ACK
-                    self.ack.clear();
-                    Packet::Ack(block_id).encode(&mut self.ack);
-
-                    self.socket.send_to(&self.ack, self.peer).await?;
-                    return Ok(data);
+                Ok(Reply::Data(data)) => return Ok(data),
+                Ok(Reply::Gap) => {
+                    // Client skipped a block of the window. Acknowledge the
+                    // last block we received in order, so it can continue
+                    // from there.
+                    self.send_ack(block_id.wrapping_sub(1)).await?;
+                    continue;
+                }
+                Ok(Reply::Error(e, msg)) => {
+                    trace!(
+                        "WRQ (peer: {}) - Received error {}: {}",
+                        &self.peer,
+                        e.code(),
+                        &msg
+                    );
+                    return Err(Error::PeerError(e, msg));
                 }
                 Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
-                    // On timeout reply with the previous ACK packet
-                    self.socket.send_to(&self.ack, self.peer).await?;
+                    self.events.emit(TransferEvent::TimedOut {
+                        block_id,
+                    });
+                    self.events.emit(TransferEvent::Retransmitted {
+                        block_id: block_id.wrapping_sub(1),
+                    });
+
+                    // On timeout acknowledge the last block we received, or
+                    // reply with the previous ACK packet.
+                    if self.unacked_blocks > 0 {
+                        self.send_ack(block_id.wrapping_sub(1)).await?;
+                    } else {
+                        self.socket.send_to(&self.ack, self.peer).await?;
+                    }
                     continue;
                 }
                 Err(e) => return Err(e.into()),
@@ -134,9 +221,29 @@ where
         Err(Error::MaxSendRetriesReached(self.peer, block_id))
     }
 
-    async fn recv_data_block(&mut self, block_id: u16) -> io::Result<Bytes> {
+    async fn send_ack(&mut self, block_id: u16) -> Result<()> {
+        self.ack.clear();
+        Packet::Ack(block_id).encode(&mut self.ack);
+        self.unacked_blocks = 0;
+
+        self.events.emit(TransferEvent::BlockAcked {
+            block_id,
+            offset: self.bytes,
+        });
+
+        self.socket.send_to(&self.ack, self.peer).await?;
+        Ok(())
+    }
+
+    /// Receive Data packet of `block_id`.
+    ///
+    /// Returns `Reply::Gap` if another block is received while the current
+    /// window has unacknowledged blocks.
+    async fn recv_data_block(&mut self, block_id: u16) -> io::Result<Reply> {
         let socket = &mut self.socket;
+        let unknown_tid = &mut self.unknown_tid;
         let peer = self.peer;
+        let report_gap = self.unacked_blocks > 0;
 
         self.buffer.resize(PACKET_DATA_HEADER_LEN + self.block_size, 0);
         let mut buf = self.buffer.split();
@@ -146,21 +253,32 @@ where
                 let (len, recved_peer) = socket.recv_from(&mut buf[..]).await?;
 
                 if recved_peer != peer {
+                    unknown_tid.reply(socket, &buf[..len], recved_peer).await;
                     continue;
                 }
 
-                if let Ok(Packet::Data(recved_block_id, _)) =
-                    Packet::decode(&buf[..len])
-                {
-                    if recved_block_id == block_id {
-                        buf.truncate(len);
-                        buf.advance(PACKET_DATA_HEADER_LEN);
-                        break;
+                match Packet::decode(&buf[..len]) {
+                    Ok(Packet::Data(recved_block_id, _)) => {
+                        if recved_block_id == block_id {
+                            buf.truncate(len);
+                            buf.advance(PACKET_DATA_HEADER_LEN);
+                            return Ok(Reply::Data(buf.freeze()));
+                        }
+
+                        if report_gap {
+                            return Ok(Reply::Gap);
+                        }
+                    }
+                    // Client aborts the transfer, or does not accept our
+                    // OACK (RFC2347).
+                    Ok(Packet::Error(e)) => {
+                        let msg = parse_error_msg(&buf[..len]).unwrap_or("");
+                        return Ok(Reply::Error(e, msg.to_string()));
                     }
+                    // Ignore the rest
+                    _ => {}
                 }
             }
-
-            Ok(buf.freeze())
         })
         .await
     }
@@ -179,10 +297,16 @@ fn build_oack_opts(config: &ServerConfig, req: &RwReq) -> Option<Opts> {
 
     if !config.ignore_client_timeout {
         opts.timeout = req.opts.timeout;
+        opts.timeout_us = req.opts.timeout_us;
     }
 
     opts.transfer_size = req.opts.transfer_size;
 
+    opts.window_size = req
+        .opts
+        .window_size
+        .map(|wsize| cmp::min(wsize, config.window_size_limit));
+
     if opts == Opts::default() {
         None
     } else {
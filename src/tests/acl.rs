@@ -0,0 +1,109 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{req, RawClient};
+use crate::packet::{self, Packet};
+use crate::server::{Acl, IpCidr};
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn cidr(s: &str) -> IpCidr {
+    s.parse().unwrap()
+}
+
+#[test]
+fn cidr_contains() {
+    assert!(cidr("10.0.0.0/8").contains(ip("10.1.2.3")));
+    assert!(!cidr("10.0.0.0/8").contains(ip("11.0.0.1")));
+    assert!(cidr("0.0.0.0/0").contains(ip("192.168.1.1")));
+    assert!(cidr("192.168.1.1").contains(ip("192.168.1.1")));
+    assert!(!cidr("192.168.1.1").contains(ip("192.168.1.2")));
+    assert!(cidr("fd00::/8").contains(ip("fd12::1")));
+    assert!(!cidr("fd00::/8").contains(ip("fe80::1")));
+    assert!(!cidr("::/0").contains(ip("10.0.0.1")));
+
+    // IPv4-mapped addresses of dual-stack sockets
+    assert!(cidr("127.0.0.0/8").contains(ip("::ffff:127.0.0.1")));
+
+    assert_eq!(cidr("10.0.0.0/8").to_string(), "10.0.0.0/8");
+}
+
+#[test]
+fn cidr_invalid() {
+    assert!("10.0.0.0/33".parse::<IpCidr>().is_err());
+    assert!("::/129".parse::<IpCidr>().is_err());
+    assert!("10.0.0.0/".parse::<IpCidr>().is_err());
+    assert!("localhost".parse::<IpCidr>().is_err());
+}
+
+#[test]
+fn acl_allow_deny() {
+    assert!(Acl::new().is_allowed(ip("10.0.0.1")));
+
+    let acl = Acl::new().allow(cidr("10.0.0.0/8")).deny(cidr("10.0.0.0/24"));
+
+    assert!(acl.is_allowed(ip("10.1.0.1")));
+    assert!(!acl.is_allowed(ip("10.0.0.1")));
+    assert!(!acl.is_allowed(ip("192.168.0.1")));
+}
+
+#[test]
+fn acl_reject() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder
+            .rrq_acl(Acl::new().deny(cidr("127.0.0.0/8")))
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Rrq(req())).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(
+            Packet::decode(&packet),
+            Ok(Packet::Error(packet::Error::PermissionDenied))
+        ));
+
+        // Write requests have their own ACL
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Wrq(req())).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(0))));
+    }));
+}
+
+#[test]
+fn acl_drop() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder
+            .wrq_acl(Acl::new().allow(cidr("10.0.0.0/8")))
+            .drop_denied_requests()
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Wrq(req())).await;
+
+        assert!(client.try_recv(Duration::from_millis(200)).await.is_none());
+    }));
+}
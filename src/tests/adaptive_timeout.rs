@@ -0,0 +1,92 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use std::time::{Duration, Instant};
+
+use super::mem_handler::mem_server;
+use super::raw_client::{req, start_server, RawClient};
+use crate::packet::Packet;
+
+/// Wait for the Data packet of `block_id`, and return the time it arrived.
+async fn recv_data(client: &mut RawClient, block_id: u16) -> Instant {
+    let packet = client.recv().await;
+
+    match Packet::decode(&packet) {
+        Ok(Packet::Data(id, _)) if id == block_id => Instant::now(),
+        p => panic!("Expected Data of block {}, got {:?}", block_id, p),
+    }
+}
+
+fn follows_rtt(min: Duration, max: Duration) {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 512 * 8]);
+        let builder =
+            builder.timeout(Duration::from_secs(2)).adaptive_timeout(min, max);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req())).await;
+
+        // Fast acknowledgments shrink the timeout to its minimum
+        for block_id in 1..=4 {
+            recv_data(&mut client, block_id).await;
+            client.send(Packet::Ack(block_id)).await;
+        }
+
+        // Block 5 is retransmitted long before the initial timeout, and
+        // the timeout doubles on every loss.
+        let sent = recv_data(&mut client, 5).await;
+        let first = recv_data(&mut client, 5).await;
+        let second = recv_data(&mut client, 5).await;
+
+        let first_wait = first - sent;
+        let second_wait = second - first;
+
+        assert!(first_wait < Duration::from_millis(500), "{:?}", first_wait);
+        assert!(second_wait >= first_wait * 3 / 2, "{:?}", second_wait);
+    }));
+}
+
+#[test]
+fn adaptive_timeout_follows_rtt() {
+    follows_rtt(Duration::from_millis(50), Duration::from_secs(5));
+}
+
+#[test]
+fn adaptive_timeout_swapped_bounds() {
+    follows_rtt(Duration::from_secs(5), Duration::from_millis(50));
+}
+
+#[test]
+fn adaptive_timeout_respects_client_timeout() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder
+            .adaptive_timeout(
+                Duration::from_millis(50),
+                Duration::from_millis(100),
+            )
+            .build()
+            .await
+            .unwrap();
+        let mut client = RawClient::new(tftpd.listen_addr().unwrap());
+        let _server = ex.spawn(tftpd.serve());
+
+        let mut req = req();
+        req.opts.timeout = Some(1);
+        client.send(Packet::Rrq(req)).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::OAck(_))));
+        client.send(Packet::Ack(0)).await;
+
+        // Negotiated timeout is used for the whole transfer
+        let sent = recv_data(&mut client, 1).await;
+        let resent = recv_data(&mut client, 1).await;
+
+        assert!(resent - sent >= Duration::from_millis(900));
+    }));
+}
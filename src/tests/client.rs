@@ -0,0 +1,153 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use futures_lite::io::{BufWriter, Cursor};
+use rand::rngs::SmallRng;
+use rand::{RngCore, SeedableRng};
+
+use super::mem_handler::mem_server;
+use crate::client::TftpClientBuilder;
+use crate::server::TftpServerBuilder;
+
+fn random_data(size: usize) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    SmallRng::from_entropy().fill_bytes(&mut data);
+    data
+}
+
+fn transfer(file_size: usize, block_size: Option<u16>) {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = random_data(file_size);
+        let (builder, written_rx) = mem_server(data.clone());
+
+        let tftpd = builder.build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let mut builder = TftpClientBuilder::new();
+        if let Some(block_size) = block_size {
+            builder = builder.block_size(block_size);
+        }
+        let client = builder.build();
+
+        // download
+        let mut received = Vec::new();
+        let len = client.get(addr, "test", &mut received).await.unwrap();
+        assert_eq!(len, file_size as u64);
+        assert_eq!(received, data);
+
+        // upload
+        let size = Some(file_size as u64);
+        let len = client.put(addr, "test", &mut &data[..], size).await.unwrap();
+        assert_eq!(len, file_size as u64);
+
+        let written = written_rx.recv().await.unwrap();
+        assert_eq!(written, data);
+    }));
+}
+
+#[test]
+fn client_transfer_0_bytes() {
+    transfer(0, None);
+    transfer(0, Some(1024));
+}
+
+#[test]
+fn client_transfer_less_than_block() {
+    transfer(1, None);
+    transfer(511, None);
+    transfer(1023, Some(1024));
+}
+
+#[test]
+fn client_transfer_block() {
+    transfer(512, None);
+    transfer(1024, Some(1024));
+}
+
+#[test]
+fn client_transfer_more_than_block() {
+    transfer(512 + 1, None);
+    transfer(512 + 511, None);
+    transfer(1024 + 1, Some(1024));
+    transfer(1024 + 1023, Some(1024));
+}
+
+#[test]
+fn client_transfer_1mb() {
+    transfer(1024 * 1024, None);
+    transfer(1024 * 1024, Some(1468));
+}
+
+#[test]
+fn client_file_not_found() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let dir = tempfile::tempdir().unwrap();
+        let tftpd = TftpServerBuilder::with_dir_ro(dir.path())
+            .unwrap()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().build();
+        let mut received = Vec::new();
+        let res = client.get(addr, "missing", &mut received).await;
+
+        assert!(matches!(
+            res,
+            Err(crate::Error::Packet(crate::packet::Error::FileNotFound))
+        ));
+    }));
+}
+
+#[test]
+fn client_block_size_ignored() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = random_data(2000);
+        let (builder, _written_rx) = mem_server(data.clone());
+
+        let tftpd = builder.ignore_client_block_size().build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Server replies with blocks of the default size instead of the
+        // requested ones.
+        let client = TftpClientBuilder::new().block_size(8).build();
+        let mut received = Vec::new();
+        let len = client.get(addr, "test", &mut received).await.unwrap();
+
+        assert_eq!(len, 2000);
+        assert_eq!(received, data);
+    }));
+}
+
+#[test]
+fn client_get_flushes_writer() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = random_data(1000);
+        let (builder, _written_rx) = mem_server(data.clone());
+        let tftpd = builder.build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Buffer is larger than the file, so nothing is written unless
+        // the writer is flushed
+        let mut writer =
+            BufWriter::with_capacity(4096, Cursor::new(Vec::new()));
+
+        let client = TftpClientBuilder::new().build();
+        client.get(addr, "test", &mut writer).await.unwrap();
+
+        assert_eq!(writer.get_ref().get_ref(), &data);
+    }));
+}
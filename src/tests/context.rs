@@ -0,0 +1,145 @@
+use async_channel::Sender;
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use futures_lite::io::{Cursor, Sink};
+use std::path::{Path, PathBuf};
+
+use super::raw_client::{start_server, RawClient};
+use crate::packet::{self, Mode, Opts, Packet, RwReq};
+use crate::server::{Handler, RequestContext, TftpServerBuilder};
+
+/// Handler that reports the context and path of the requests and rejects
+/// them.
+struct ContextHandler {
+    ctx_tx: Sender<(RequestContext, PathBuf)>,
+}
+
+#[crate::async_trait]
+impl Handler for ContextHandler {
+    type Reader = Cursor<Vec<u8>>;
+    type Writer = Sink;
+
+    async fn read_req_open(
+        &mut self,
+        ctx: &RequestContext,
+        path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        self.ctx_tx.send((ctx.clone(), path.to_owned())).await.unwrap();
+        Err(packet::Error::PermissionDenied)
+    }
+
+    async fn write_req_open(
+        &mut self,
+        ctx: &RequestContext,
+        path: &Path,
+        _size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error> {
+        self.ctx_tx.send((ctx.clone(), path.to_owned())).await.unwrap();
+        Err(packet::Error::PermissionDenied)
+    }
+}
+
+#[test]
+fn request_context() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (ctx_tx, ctx_rx) = async_channel::unbounded();
+        let handler = ContextHandler {
+            ctx_tx,
+        };
+
+        let tftpd = TftpServerBuilder::with_handler(handler)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let listen_addr = tftpd.listen_addr().unwrap();
+        let mut client = RawClient::new(listen_addr);
+        let _server = ex.spawn(tftpd.serve());
+
+        let opts = Opts {
+            block_size: Some(1024),
+            transfer_size: Some(0),
+            ..Opts::default()
+        };
+
+        client
+            .send(Packet::Rrq(RwReq {
+                filename: b"dir/file.txt".to_vec(),
+                mode: Mode::Netascii,
+                opts: opts.clone(),
+            }))
+            .await;
+
+        let (ctx, path) = ctx_rx.recv().await.unwrap();
+        assert_eq!(ctx.peer(), client.local_addr());
+        assert_eq!(ctx.local_addr(), listen_addr);
+        assert_eq!(ctx.mode(), Mode::Netascii);
+        assert_eq!(ctx.opts(), &opts);
+        assert_eq!(ctx.filename(), "dir/file.txt");
+        assert_eq!(ctx.filename_bytes(), b"dir/file.txt");
+        assert_eq!(path, Path::new("dir/file.txt"));
+
+        let packet = client.recv().await;
+        assert!(matches!(
+            Packet::decode(&packet),
+            Ok(Packet::Error(packet::Error::PermissionDenied))
+        ));
+
+        let client = RawClient::new(listen_addr);
+
+        client
+            .send(Packet::Wrq(RwReq {
+                filename: b"upload".to_vec(),
+                mode: Mode::Octet,
+                opts: Opts::default(),
+            }))
+            .await;
+
+        let (ctx, path) = ctx_rx.recv().await.unwrap();
+        assert_eq!(ctx.peer(), client.local_addr());
+        assert_eq!(ctx.mode(), Mode::Octet);
+        assert_eq!(ctx.opts(), &Opts::default());
+        assert_eq!(ctx.filename(), "upload");
+        assert_eq!(path, Path::new("upload"));
+    }));
+}
+
+#[test]
+fn request_context_non_utf8_filename() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (ctx_tx, ctx_rx) = async_channel::unbounded();
+        let handler = ContextHandler {
+            ctx_tx,
+        };
+
+        let builder = TftpServerBuilder::with_handler(handler)
+            .bind("127.0.0.1:0".parse().unwrap());
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        // Latin-1 encoded "café"
+        client.send_raw(b"\x00\x01caf\xe9\0octet\0").await;
+
+        let (ctx, path) = ctx_rx.recv().await.unwrap();
+        assert_eq!(ctx.filename_bytes(), b"caf\xe9");
+        assert_eq!(ctx.filename(), "caf\u{fffd}");
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            assert_eq!(path.as_os_str().as_bytes(), b"caf\xe9");
+        }
+        #[cfg(not(unix))]
+        assert_eq!(path, Path::new("caf\u{fffd}"));
+
+        // Request reaches the handler, which replies
+        let packet = client.recv().await;
+        assert!(matches!(
+            Packet::decode(&packet),
+            Ok(Packet::Error(packet::Error::PermissionDenied))
+        ));
+    }));
+}
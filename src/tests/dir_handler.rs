@@ -0,0 +1,300 @@
+use async_executor::Executor;
+use async_io::Timer;
+use futures_lite::future::block_on;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use super::raw_client::RawClient;
+use crate::client::TftpClientBuilder;
+use crate::packet::{self, Mode, Opts, Packet, RwReq};
+use crate::server::handlers::{DirHandler, DirHandlerMode, OverwritePolicy};
+use crate::server::TftpServerBuilder;
+use crate::Error;
+
+/// Sorted names of the files in `dir`.
+fn dir_entries(dir: &Path) -> Vec<String> {
+    let mut entries: Vec<String> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .collect();
+    entries.sort();
+    entries
+}
+
+/// Wait until `dir` has exactly the `expected` files.
+async fn wait_dir_entries(dir: &Path, expected: &[&str]) {
+    for _ in 0..100 {
+        if dir_entries(dir) == expected {
+            return;
+        }
+
+        Timer::after(Duration::from_millis(20)).await;
+    }
+
+    assert_eq!(dir_entries(dir), expected);
+}
+
+#[test]
+fn upload_completed() {
+    let ex = Executor::new();
+    let dir = tempfile::tempdir().unwrap();
+
+    block_on(ex.run(async {
+        let tftpd = TftpServerBuilder::with_dir_wo(dir.path())
+            .unwrap()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        fs::write(dir.path().join("file"), b"old content").unwrap();
+
+        let client = TftpClientBuilder::new().build();
+        let data = vec![3u8; 1000];
+        let size = Some(data.len() as u64);
+        client.put(addr, "file", &mut &data[..], size).await.unwrap();
+
+        wait_dir_entries(dir.path(), &["file"]).await;
+        assert_eq!(fs::read(dir.path().join("file")).unwrap(), data);
+    }));
+}
+
+#[test]
+fn upload_interrupted() {
+    let ex = Executor::new();
+    let dir = tempfile::tempdir().unwrap();
+
+    block_on(ex.run(async {
+        let tftpd = TftpServerBuilder::with_dir_wo(dir.path())
+            .unwrap()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .timeout(Duration::from_millis(100))
+            .max_send_retries(0)
+            .build()
+            .await
+            .unwrap();
+        let mut client = RawClient::new(tftpd.listen_addr().unwrap());
+        let _server = ex.spawn(tftpd.serve());
+
+        fs::write(dir.path().join("file"), b"old content").unwrap();
+
+        client
+            .send(Packet::Wrq(RwReq {
+                filename: b"file".to_vec(),
+                mode: Mode::Octet,
+                opts: Opts {
+                    transfer_size: Some(1000),
+                    ..Opts::default()
+                },
+            }))
+            .await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::OAck(_))));
+
+        client.send(Packet::Data(1, &[3u8; 512])).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(1))));
+
+        // Client stops sending, so server gives up after retransmitting
+        // the last ACK.
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(1))));
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Error(_))));
+
+        // Temporary file is deleted and the old file is intact
+        wait_dir_entries(dir.path(), &["file"]).await;
+        assert_eq!(fs::read(dir.path().join("file")).unwrap(), b"old content");
+    }));
+}
+
+#[test]
+fn upload_no_overwrite() {
+    let ex = Executor::new();
+    let dir = tempfile::tempdir().unwrap();
+
+    block_on(ex.run(async {
+        let handler = DirHandler::new(dir.path(), DirHandlerMode::WriteOnly)
+            .unwrap()
+            .overwrite_policy(OverwritePolicy::NoOverwrite);
+
+        let tftpd = TftpServerBuilder::with_handler(handler)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().build();
+
+        client.put(addr, "file", &mut &b"first"[..], None).await.unwrap();
+        wait_dir_entries(dir.path(), &["file"]).await;
+
+        let res = client.put(addr, "file", &mut &b"second"[..], None).await;
+        assert!(matches!(
+            res,
+            Err(Error::Packet(packet::Error::FileAlreadyExists))
+        ));
+
+        wait_dir_entries(dir.path(), &["file"]).await;
+        assert_eq!(fs::read(dir.path().join("file")).unwrap(), b"first");
+    }));
+}
+
+#[test]
+fn upload_no_overwrite_concurrent() {
+    let ex = Executor::new();
+    let dir = tempfile::tempdir().unwrap();
+
+    block_on(ex.run(async {
+        let handler = DirHandler::new(dir.path(), DirHandlerMode::WriteOnly)
+            .unwrap()
+            .overwrite_policy(OverwritePolicy::NoOverwrite);
+
+        let tftpd = TftpServerBuilder::with_handler(handler)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let req = || {
+            Packet::Wrq(RwReq {
+                filename: b"file".to_vec(),
+                mode: Mode::Octet,
+                opts: Opts::default(),
+            })
+        };
+
+        // Both requests start before any of them is stored
+        let mut first = RawClient::new(addr);
+        let mut second = RawClient::new(addr);
+
+        first.send(req()).await;
+        assert_eq!(first.recv().await, Packet::Ack(0).to_bytes());
+        second.send(req()).await;
+        assert_eq!(second.recv().await, Packet::Ack(0).to_bytes());
+
+        first.send(Packet::Data(1, b"first")).await;
+        assert_eq!(first.recv().await, Packet::Ack(1).to_bytes());
+
+        // Last block of the second one is never acknowledged
+        second.send(Packet::Data(1, b"second")).await;
+        let packet = second.recv().await;
+        assert!(matches!(
+            Packet::decode(&packet),
+            Ok(Packet::Error(packet::Error::FileAlreadyExists))
+        ));
+
+        wait_dir_entries(dir.path(), &["file"]).await;
+        assert_eq!(fs::read(dir.path().join("file")).unwrap(), b"first");
+    }));
+}
+
+#[test]
+fn upload_versioned() {
+    let ex = Executor::new();
+    let dir = tempfile::tempdir().unwrap();
+
+    block_on(ex.run(async {
+        let handler = DirHandler::new(dir.path(), DirHandlerMode::WriteOnly)
+            .unwrap()
+            .overwrite_policy(OverwritePolicy::Versioned);
+
+        let tftpd = TftpServerBuilder::with_handler(handler)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().build();
+
+        client.put(addr, "file", &mut &b"0"[..], None).await.unwrap();
+        wait_dir_entries(dir.path(), &["file"]).await;
+
+        client.put(addr, "file", &mut &b"1"[..], None).await.unwrap();
+        wait_dir_entries(dir.path(), &["file", "file.1"]).await;
+
+        client.put(addr, "file", &mut &b"2"[..], None).await.unwrap();
+        wait_dir_entries(dir.path(), &["file", "file.1", "file.2"]).await;
+
+        assert_eq!(fs::read(dir.path().join("file")).unwrap(), b"0");
+        assert_eq!(fs::read(dir.path().join("file.1")).unwrap(), b"1");
+        assert_eq!(fs::read(dir.path().join("file.2")).unwrap(), b"2");
+    }));
+}
+
+#[test]
+fn upload_store_failed() {
+    let ex = Executor::new();
+    let dir = tempfile::tempdir().unwrap();
+
+    block_on(ex.run(async {
+        let tftpd = TftpServerBuilder::with_dir_wo(dir.path())
+            .unwrap()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Upload can not replace a directory
+        fs::create_dir(dir.path().join("file")).unwrap();
+
+        let client = TftpClientBuilder::new().build();
+        let res = client.put(addr, "file", &mut &b"data"[..], None).await;
+        assert!(matches!(res, Err(Error::Packet(_))), "{:?}", res);
+
+        wait_dir_entries(dir.path(), &["file"]).await;
+        assert!(dir.path().join("file").is_dir());
+    }));
+}
+
+#[test]
+fn tmp_file_refused() {
+    let ex = Executor::new();
+    let dir = tempfile::tempdir().unwrap();
+
+    block_on(ex.run(async {
+        let tftpd = TftpServerBuilder::with_dir_rw(dir.path())
+            .unwrap()
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Left behind by an interrupted upload
+        fs::write(dir.path().join(".file.0.tmp"), b"partial").unwrap();
+
+        let client = TftpClientBuilder::new().build();
+
+        let mut received = Vec::new();
+        let res = client.get(addr, ".file.0.tmp", &mut received).await;
+        assert!(matches!(res, Err(Error::Packet(packet::Error::FileNotFound))));
+
+        let res =
+            client.put(addr, ".file.0.tmp", &mut &b"data"[..], None).await;
+        assert!(matches!(
+            res,
+            Err(Error::Packet(packet::Error::PermissionDenied))
+        ));
+
+        assert_eq!(
+            fs::read(dir.path().join(".file.0.tmp")).unwrap(),
+            b"partial"
+        );
+    }));
+}
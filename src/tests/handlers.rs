@@ -3,12 +3,11 @@
 
 use async_channel::Sender;
 use futures_lite::io::Sink;
-use std::net::SocketAddr;
 use std::path::Path;
 
 use super::random_file::RandomFile;
 use crate::packet;
-use crate::server::Handler;
+use crate::server::{Handler, RequestContext};
 
 pub struct RandomHandler {
     md5_tx: Option<Sender<md5::Digest>>,
@@ -31,7 +30,7 @@ impl Handler for RandomHandler {
 
     async fn read_req_open(
         &mut self,
-        _client: &SocketAddr,
+        _ctx: &RequestContext,
         _path: &Path,
     ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
         let md5_tx = self.md5_tx.take().expect("md5_tx already consumed");
@@ -40,7 +39,7 @@ impl Handler for RandomHandler {
 
     async fn write_req_open(
         &mut self,
-        _client: &SocketAddr,
+        _ctx: &RequestContext,
         _path: &Path,
         _size: Option<u64>,
     ) -> Result<Self::Writer, packet::Error> {
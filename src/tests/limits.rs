@@ -0,0 +1,136 @@
+use async_executor::Executor;
+use async_io::Timer;
+use futures_lite::future::block_on;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{rrq, RawClient};
+use crate::packet::{self, Packet};
+
+async fn assert_data(client: &mut RawClient) {
+    let packet = client.recv().await;
+    assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+}
+
+async fn assert_rejected(client: &mut RawClient) {
+    let packet = client.recv().await;
+    assert!(matches!(
+        Packet::decode(&packet),
+        Ok(Packet::Error(packet::Error::Msg(_)))
+    ));
+}
+
+#[test]
+fn limits_max_transfers() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let tftpd = builder.max_transfers(1).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let mut client1 = RawClient::new(addr);
+        client1.send(rrq()).await;
+        assert_data(&mut client1).await;
+
+        let mut client2 = RawClient::new(addr);
+        client2.send(rrq()).await;
+        assert_rejected(&mut client2).await;
+
+        // Limit is released when the transfer finishes
+        client1.send(Packet::Ack(1)).await;
+        Timer::after(Duration::from_millis(100)).await;
+
+        let mut client3 = RawClient::new(addr);
+        client3.send(rrq()).await;
+        assert_data(&mut client3).await;
+        client3.send(Packet::Ack(1)).await;
+    }));
+}
+
+#[test]
+fn limits_max_transfers_per_ip() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let tftpd = builder.max_transfers_per_ip(2).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Clients have different ports but the same IP
+        let mut client1 = RawClient::new(addr);
+        client1.send(rrq()).await;
+        assert_data(&mut client1).await;
+
+        let mut client2 = RawClient::new(addr);
+        client2.send(rrq()).await;
+        assert_data(&mut client2).await;
+
+        let mut client3 = RawClient::new(addr);
+        client3.send(rrq()).await;
+        assert_rejected(&mut client3).await;
+
+        client1.send(Packet::Ack(1)).await;
+        client2.send(Packet::Ack(1)).await;
+    }));
+}
+
+#[test]
+fn limits_queue() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let tftpd = builder
+            .max_transfers(1)
+            .transfer_queue_timeout(Duration::from_secs(5))
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let mut client1 = RawClient::new(addr);
+        client1.send(rrq()).await;
+        assert_data(&mut client1).await;
+
+        // Request waits for the first transfer
+        let mut client2 = RawClient::new(addr);
+        client2.send(rrq()).await;
+        assert!(client2.try_recv(Duration::from_millis(200)).await.is_none());
+
+        client1.send(Packet::Ack(1)).await;
+
+        assert_data(&mut client2).await;
+        client2.send(Packet::Ack(1)).await;
+    }));
+}
+
+#[test]
+fn limits_queue_timeout() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let tftpd = builder
+            .max_transfers(1)
+            .transfer_queue_timeout(Duration::from_millis(200))
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let mut client1 = RawClient::new(addr);
+        client1.send(rrq()).await;
+        assert_data(&mut client1).await;
+
+        let mut client2 = RawClient::new(addr);
+        client2.send(rrq()).await;
+        assert_rejected(&mut client2).await;
+
+        client1.send(Packet::Ack(1)).await;
+    }));
+}
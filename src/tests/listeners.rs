@@ -0,0 +1,138 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use std::net::{SocketAddr, UdpSocket};
+
+use super::mem_handler::mem_server;
+use super::raw_client::{rrq, RawClient};
+use crate::client::TftpClientBuilder;
+use crate::packet::Packet;
+
+// Other systems, such as macOS, configure only 127.0.0.1 on loopback
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+#[test]
+fn listeners_reply_from_request_address() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let addrs: Vec<SocketAddr> = vec![
+            "127.0.0.1:0".parse().unwrap(),
+            "127.0.0.2:0".parse().unwrap(),
+        ];
+
+        let tftpd = builder.bind_addrs(addrs).build().await.unwrap();
+        let listen_addrs = tftpd.listen_addrs();
+        let _server = ex.spawn(tftpd.serve());
+
+        assert_eq!(listen_addrs.len(), 2);
+
+        for addr in listen_addrs {
+            let mut client = RawClient::new(addr);
+            client.send(rrq()).await;
+
+            let packet = client.recv().await;
+            assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+            assert_eq!(client.peer().unwrap().ip(), addr.ip());
+
+            client.send(Packet::Ack(1)).await;
+        }
+    }));
+}
+
+#[test]
+fn listeners_dual_stack() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = vec![7u8; 1000];
+        let (builder, _written_rx) = mem_server(data.clone());
+
+        let addrs: Vec<SocketAddr> =
+            vec!["127.0.0.1:0".parse().unwrap(), "[::1]:0".parse().unwrap()];
+
+        let tftpd = builder.bind_addrs(addrs).build().await.unwrap();
+        let listen_addrs = tftpd.listen_addrs();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().build();
+
+        for addr in listen_addrs {
+            let mut received = Vec::new();
+            client.get(addr, "test", &mut received).await.unwrap();
+            assert_eq!(received, data);
+        }
+    }));
+}
+
+#[test]
+fn listeners_dual_stack_wildcard() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = vec![7u8; 1000];
+        let (builder, _written_rx) = mem_server(data.clone());
+
+        let port =
+            UdpSocket::bind("0.0.0.0:0").unwrap().local_addr().unwrap().port();
+        let addrs: Vec<SocketAddr> = vec![
+            ([0, 0, 0, 0], port).into(),
+            ([0u16, 0, 0, 0, 0, 0, 0, 0], port).into(),
+        ];
+
+        let tftpd = builder.bind_addrs(addrs).build().await.unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().build();
+        let loopbacks: Vec<SocketAddr> = vec![
+            ([127, 0, 0, 1], port).into(),
+            ([0, 0, 0, 0, 0, 0, 0, 1], port).into(),
+        ];
+
+        for addr in loopbacks {
+            let mut received = Vec::new();
+            client.get(addr, "test", &mut received).await.unwrap();
+            assert_eq!(received, data);
+        }
+    }));
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn wildcard_reply(single_port: bool) {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let mut builder = builder.bind("0.0.0.0:0".parse().unwrap());
+        if single_port {
+            builder = builder.single_port();
+        }
+        let tftpd = builder.build().await.unwrap();
+        let port = tftpd.listen_addr().unwrap().port();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Kernel would pick 127.0.0.1 as source address of the reply
+        let addr: SocketAddr = ([127, 0, 0, 2], port).into();
+        let mut client = RawClient::new(addr);
+        client.send(rrq()).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+        assert_eq!(client.peer().unwrap().ip(), addr.ip());
+
+        client.send(Packet::Ack(1)).await;
+    }));
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn listeners_wildcard_reply_from_request_address() {
+    wildcard_reply(false);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn listeners_wildcard_single_port_reply_from_request_address() {
+    wildcard_reply(true);
+}
@@ -0,0 +1,104 @@
+use async_channel::{Receiver, Sender};
+use futures_lite::io::Cursor;
+use futures_lite::AsyncWrite;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use crate::packet;
+use crate::server::{Handler, RequestContext, TftpServerBuilder};
+
+/// Handler that serves `data` on read requests and sends the data of
+/// write requests to a channel.
+pub struct MemHandler {
+    data: Vec<u8>,
+    written_tx: Sender<Vec<u8>>,
+}
+
+impl MemHandler {
+    pub fn new(data: Vec<u8>, written_tx: Sender<Vec<u8>>) -> Self {
+        MemHandler {
+            data,
+            written_tx,
+        }
+    }
+}
+
+/// Builder of a server on a local port that serves `data` with
+/// `MemHandler`, and the receiver of the data that clients write.
+pub fn mem_server(
+    data: Vec<u8>,
+) -> (TftpServerBuilder<MemHandler>, Receiver<Vec<u8>>) {
+    let (written_tx, written_rx) = async_channel::bounded(1);
+    let handler = MemHandler::new(data, written_tx);
+    let builder = TftpServerBuilder::with_handler(handler)
+        .bind("127.0.0.1:0".parse().unwrap());
+
+    (builder, written_rx)
+}
+
+#[crate::async_trait]
+impl Handler for MemHandler {
+    type Reader = Cursor<Vec<u8>>;
+    type Writer = MemWriter;
+
+    async fn read_req_open(
+        &mut self,
+        _ctx: &RequestContext,
+        _path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        let len = self.data.len() as u64;
+        Ok((Cursor::new(self.data.clone()), Some(len)))
+    }
+
+    async fn write_req_open(
+        &mut self,
+        _ctx: &RequestContext,
+        _path: &Path,
+        _size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error> {
+        Ok(MemWriter {
+            data: Vec::new(),
+            written_tx: self.written_tx.clone(),
+        })
+    }
+}
+
+/// Writer that sends all the written data to a channel when dropped.
+pub struct MemWriter {
+    data: Vec<u8>,
+    written_tx: Sender<Vec<u8>>,
+}
+
+impl AsyncWrite for MemWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.data.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MemWriter {
+    fn drop(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        let _ = self.written_tx.try_send(data);
+    }
+}
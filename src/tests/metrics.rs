@@ -0,0 +1,76 @@
+#![cfg(feature = "prometheus")]
+
+use async_executor::Executor;
+use async_io::{Async, Timer};
+use futures_lite::future::block_on;
+use futures_lite::{AsyncReadExt, AsyncWriteExt};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::RawClient;
+use crate::packet::{Mode, Opts, Packet, RwReq};
+
+async fn http_get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = Async::<TcpStream>::connect(addr).await.unwrap();
+    let req = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(req.as_bytes()).await.unwrap();
+
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).await.unwrap();
+    resp
+}
+
+#[test]
+fn metrics_http() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder
+            .metrics_addr("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let metrics_addr = tftpd.metrics_addr().unwrap();
+        let handle = tftpd.handle();
+        let server = ex.spawn(tftpd.serve());
+
+        let mut client = RawClient::new(addr);
+        let req = RwReq {
+            filename: b"test".to_vec(),
+            mode: Mode::Octet,
+            opts: Opts::default(),
+        };
+        client.send(Packet::Rrq(req)).await;
+        client.recv().await;
+        client.send(Packet::Ack(1)).await;
+
+        while handle.stats().completed_transfers == 0 {
+            Timer::after(Duration::from_millis(10)).await;
+        }
+
+        let resp = http_get(metrics_addr, "/metrics").await;
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(resp.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(resp.contains("\ntftp_active_transfers 0\n"));
+        assert!(
+            resp.contains("\ntftp_transfers_total{result=\"completed\"} 1\n")
+        );
+        assert!(resp.contains("\ntftp_sent_bytes_total 100\n"));
+        assert!(resp.contains(
+            "\ntftp_transfer_duration_seconds_bucket{le=\"+Inf\"} 1\n"
+        ));
+        assert!(resp.contains("\ntftp_transfer_duration_seconds_count 1\n"));
+
+        let resp = http_get(metrics_addr, "/other").await;
+        assert!(resp.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+        // Metrics stop with the server
+        handle.shutdown(Duration::ZERO);
+        server.await.unwrap();
+        assert!(Async::<TcpStream>::connect(metrics_addr).await.is_err());
+    }));
+}
@@ -1,7 +1,32 @@
 #![cfg(test)]
 
+mod acl;
+mod adaptive_timeout;
+mod client;
+mod context;
+mod dir_handler;
 mod external_client;
 mod handlers;
+mod limits;
+mod listeners;
+mod mem_handler;
+mod metrics;
+mod mode;
+mod netascii;
+mod observer;
+mod option_negotiation;
+mod outcome;
 mod packet;
+mod peer_error;
+mod port_range;
 mod random_file;
+mod raw_client;
 mod rrq;
+mod shared_handler;
+mod shutdown;
+mod single_port;
+mod stats;
+mod throttle;
+mod unknown_tid;
+mod utimeout;
+mod window;
@@ -0,0 +1,53 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+
+use super::mem_handler::mem_server;
+use super::raw_client::start_server;
+use crate::parse::parse_error_msg;
+
+/// Code and message of the error that server replies to `req` with.
+fn rejected(req: &[u8]) -> (u16, String) {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(Vec::new());
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send_raw(req).await;
+
+        let packet = client.recv().await;
+        assert_eq!(&packet[..2], &[0, 5], "expected error: {:?}", packet);
+
+        let code = u16::from_be_bytes([packet[2], packet[3]]);
+        let msg = parse_error_msg(&packet).unwrap().to_owned();
+        (code, msg)
+    }))
+}
+
+#[test]
+fn rrq_mail() {
+    let (code, msg) = rejected(b"\x00\x01test\0mail\0");
+    assert_eq!(code, 4);
+    assert_eq!(msg, "Unsupported transfer mode 'mail'");
+}
+
+#[test]
+fn wrq_mail() {
+    let (code, msg) = rejected(b"\x00\x02test\0MAIL\0");
+    assert_eq!(code, 4);
+    assert_eq!(msg, "Unsupported transfer mode 'mail'");
+}
+
+#[test]
+fn rrq_unknown_mode() {
+    let (code, msg) = rejected(b"\x00\x01test\0binary\0");
+    assert_eq!(code, 4);
+    assert_eq!(msg, "Unknown transfer mode 'binary'");
+}
+
+#[test]
+fn wrq_unknown_mode() {
+    let (code, msg) = rejected(b"\x00\x02test\0octex\0tsize\x001024\0");
+    assert_eq!(code, 4);
+    assert_eq!(msg, "Unknown transfer mode 'octex'");
+}
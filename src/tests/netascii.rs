@@ -0,0 +1,188 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use futures_lite::io::Cursor;
+use futures_lite::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::mem_handler::mem_server;
+use super::raw_client::start_server;
+use crate::netascii::{NetasciiReader, NetasciiWriter};
+use crate::packet::{Mode, Opts, Packet, RwReq};
+
+const DECODED: &[u8] = b"line 1\nline 2\r\n\rline 3\n\n";
+const ENCODED: &[u8] = b"line 1\r\nline 2\r\0\r\n\r\0line 3\r\n\r\n";
+
+fn req(filename: &str) -> RwReq {
+    RwReq {
+        filename: filename.as_bytes().to_vec(),
+        mode: Mode::Netascii,
+        opts: Opts::default(),
+    }
+}
+
+#[test]
+fn encode() {
+    block_on(async {
+        // Small reads split the encoded pairs
+        for chunk_len in 1..=ENCODED.len() {
+            let mut reader = NetasciiReader::new(Cursor::new(DECODED));
+            let mut encoded = Vec::new();
+            let mut buf = vec![0u8; chunk_len];
+
+            loop {
+                match reader.read(&mut buf).await.unwrap() {
+                    0 => break,
+                    len => encoded.extend_from_slice(&buf[..len]),
+                }
+            }
+
+            assert_eq!(encoded, ENCODED);
+        }
+    });
+}
+
+/// Reader that fails once and then reaches EOF.
+struct FailOnce(bool);
+
+impl AsyncRead for FailOnce {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context,
+        _buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.0 {
+            return Poll::Ready(Ok(0));
+        }
+
+        self.0 = true;
+        Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()))
+    }
+}
+
+#[test]
+fn encode_error() {
+    block_on(async {
+        let inner = Cursor::new(b"a\n").chain(FailOnce(false));
+        let mut reader = NetasciiReader::new(inner);
+        let mut buf = vec![0u8; 16];
+
+        // Error that happens after some data is reported on next read
+        assert_eq!(reader.read(&mut buf).await.unwrap(), 3);
+        assert_eq!(&buf[..3], b"a\r\n");
+        assert!(reader.read(&mut buf).await.is_err());
+    });
+}
+
+#[test]
+fn decode() {
+    block_on(async {
+        // Small writes split the encoded pairs
+        for chunk_len in 1..=ENCODED.len() {
+            let mut decoded = Vec::new();
+            let mut writer = NetasciiWriter::new(&mut decoded);
+
+            for chunk in ENCODED.chunks(chunk_len) {
+                writer.write_all(chunk).await.unwrap();
+            }
+
+            writer.close().await.unwrap();
+            drop(writer);
+
+            assert_eq!(decoded, DECODED);
+        }
+    });
+}
+
+#[test]
+fn decode_invalid() {
+    block_on(async {
+        let mut decoded = Vec::new();
+        let mut writer = NetasciiWriter::new(&mut decoded);
+
+        writer.write_all(b"a\rb\r\r\n\r").await.unwrap();
+        writer.close().await.unwrap();
+        drop(writer);
+
+        assert_eq!(decoded, b"a\rb\r\n\r");
+    });
+}
+
+#[test]
+fn rrq_netascii() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        // 512 bytes are encoded to 768, so the block boundary moves
+        let data: Vec<u8> = b"ab\n".repeat(512 / 3 + 1)[..512].to_vec();
+        let encoded: Vec<u8> =
+            b"ab\r\n".repeat(512 / 3 + 1)[..512 / 3 * 4 + 2].to_vec();
+        let (builder, _written_rx) = mem_server(data);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req("test"))).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(1, d)) if d == &encoded[..512]));
+        client.send(Packet::Ack(1)).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(2, d)) if d == &encoded[512..]));
+        client.send(Packet::Ack(2)).await;
+    }));
+}
+
+#[test]
+fn rrq_netascii_no_tsize() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(b"a\nb\n".to_vec());
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        let mut req = req("test");
+        req.opts.transfer_size = Some(0);
+        client.send(Packet::Rrq(req)).await;
+
+        // Size of the encoded data is unknown, so `tsize` is not negotiated
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(1, d)) if d == b"a\r\nb\r\n"));
+        client.send(Packet::Ack(1)).await;
+    }));
+}
+
+#[test]
+fn wrq_netascii() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, written_rx) = mem_server(Vec::new());
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Wrq(req("test"))).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(0))));
+
+        // `CR LF` is split between the blocks
+        let mut block = b"x".repeat(511);
+        block.push(b'\r');
+        client.send(Packet::Data(1, &block)).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(1))));
+
+        client.send(Packet::Data(2, b"\ny\r\0")).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(2))));
+
+        let mut expected = b"x".repeat(511);
+        expected.extend_from_slice(b"\ny\r");
+        assert_eq!(written_rx.recv().await.unwrap(), expected);
+    }));
+}
@@ -0,0 +1,184 @@
+use async_channel::{Receiver, Sender};
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{req, start_server, RawClient};
+use crate::packet::Packet;
+use crate::server::{RequestContext, TransferEvent};
+
+/// Owned copy of `TransferEvent`.
+#[derive(Debug, PartialEq)]
+enum Event {
+    Requested,
+    Negotiated,
+    Sent(u16, u64, usize),
+    Received(u16, u64, usize),
+    Acked(u16, u64),
+    TimedOut(u16),
+    Retransmitted(u16),
+    Completed(u64),
+    Failed(u64),
+}
+
+fn recorder(
+) -> (impl Fn(&RequestContext, &TransferEvent) + Send + Sync, Receiver<Event>) {
+    let (tx, rx): (Sender<Event>, _) = async_channel::unbounded();
+
+    let observer = move |ctx: &RequestContext, event: &TransferEvent| {
+        assert_eq!(ctx.filename(), "test");
+
+        let event = match *event {
+            TransferEvent::Requested => Event::Requested,
+            TransferEvent::Negotiated(_) => Event::Negotiated,
+            TransferEvent::BlockSent {
+                block_id,
+                offset,
+                len,
+            } => Event::Sent(block_id, offset, len),
+            TransferEvent::BlockReceived {
+                block_id,
+                offset,
+                len,
+            } => Event::Received(block_id, offset, len),
+            TransferEvent::BlockAcked {
+                block_id,
+                offset,
+            } => Event::Acked(block_id, offset),
+            TransferEvent::TimedOut {
+                block_id,
+            } => Event::TimedOut(block_id),
+            TransferEvent::Retransmitted {
+                block_id,
+            } => Event::Retransmitted(block_id),
+            TransferEvent::Completed {
+                bytes,
+            } => Event::Completed(bytes),
+            TransferEvent::Failed {
+                bytes,
+                ..
+            } => Event::Failed(bytes),
+        };
+
+        tx.try_send(event).unwrap();
+    };
+
+    (observer, rx)
+}
+
+async fn recv_events(rx: &Receiver<Event>, count: usize) -> Vec<Event> {
+    let mut events = Vec::new();
+
+    for _ in 0..count {
+        events.push(rx.recv().await.unwrap());
+    }
+
+    events
+}
+
+#[test]
+fn observer_rrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 600]);
+        let (observer, events) = recorder();
+        let builder = builder.observer(observer);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        let mut req = req();
+        req.opts.transfer_size = Some(0);
+        client.send(Packet::Rrq(req)).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::OAck(_))));
+        client.send(Packet::Ack(0)).await;
+
+        client.recv().await;
+        client.send(Packet::Ack(1)).await;
+        client.recv().await;
+        client.send(Packet::Ack(2)).await;
+
+        assert_eq!(
+            recv_events(&events, 7).await,
+            vec![
+                Event::Requested,
+                Event::Negotiated,
+                Event::Sent(1, 0, 512),
+                Event::Acked(1, 512),
+                Event::Sent(2, 512, 88),
+                Event::Acked(2, 600),
+                Event::Completed(600),
+            ]
+        );
+    }));
+}
+
+#[test]
+fn observer_wrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(Vec::new());
+        let (observer, events) = recorder();
+        let builder = builder.observer(observer);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Wrq(req())).await;
+        client.recv().await;
+
+        client.send(Packet::Data(1, &[7u8; 512])).await;
+        client.recv().await;
+        client.send(Packet::Data(2, b"abc")).await;
+        client.recv().await;
+
+        assert_eq!(
+            recv_events(&events, 6).await,
+            vec![
+                Event::Requested,
+                Event::Received(1, 0, 512),
+                Event::Acked(1, 512),
+                Event::Received(2, 512, 3),
+                Event::Acked(2, 515),
+                Event::Completed(515),
+            ]
+        );
+    }));
+}
+
+#[test]
+fn observer_failed() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let (observer, events) = recorder();
+
+        let tftpd = builder
+            .timeout(Duration::from_millis(100))
+            .max_send_retries(1)
+            .observer(observer)
+            .build()
+            .await
+            .unwrap();
+        let client = RawClient::new(tftpd.listen_addr().unwrap());
+        let _server = ex.spawn(tftpd.serve());
+
+        // Client never acknowledges the data
+        client.send(Packet::Rrq(req())).await;
+
+        assert_eq!(
+            recv_events(&events, 7).await,
+            vec![
+                Event::Requested,
+                Event::Sent(1, 0, 100),
+                Event::TimedOut(1),
+                Event::Retransmitted(1),
+                Event::Sent(1, 0, 100),
+                Event::TimedOut(1),
+                Event::Failed(0),
+            ]
+        );
+    }));
+}
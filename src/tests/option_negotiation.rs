@@ -0,0 +1,214 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use futures_lite::io::{Cursor, Sink};
+use std::path::Path;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{req, req_opts, start_server, RawClient};
+use crate::packet::{self, Opts, Packet};
+use crate::server::{Handler, RequestContext, ServerHandle, TftpServerBuilder};
+
+/// Handler that rejects requests with `windowsize` option.
+struct NoWindowHandler;
+
+#[crate::async_trait]
+impl Handler for NoWindowHandler {
+    type Reader = Cursor<Vec<u8>>;
+    type Writer = Sink;
+
+    async fn read_req_open(
+        &mut self,
+        ctx: &RequestContext,
+        _path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        if ctx.opts().window_size.is_some() {
+            return Err(packet::Error::OptionNegotiation);
+        }
+
+        Ok((Cursor::new(vec![7u8; 100]), None))
+    }
+
+    async fn write_req_open(
+        &mut self,
+        _ctx: &RequestContext,
+        _path: &Path,
+        _size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error> {
+        Ok(futures_lite::io::sink())
+    }
+}
+
+fn block_size(size: u16) -> Opts {
+    Opts {
+        block_size: Some(size),
+        ..Opts::default()
+    }
+}
+
+fn transfer_size(size: u64) -> Opts {
+    Opts {
+        transfer_size: Some(size),
+        ..Opts::default()
+    }
+}
+
+fn is_option_error(packet: &[u8]) -> bool {
+    matches!(
+        Packet::decode(packet),
+        Ok(Packet::Error(packet::Error::OptionNegotiation))
+    )
+}
+
+fn is_oack(packet: &[u8]) -> bool {
+    matches!(Packet::decode(packet), Ok(Packet::OAck(_)))
+}
+
+async fn wait_failed(handle: &ServerHandle, count: u64) {
+    while handle.stats().failed_transfers < count {
+        async_io::Timer::after(Duration::from_millis(10)).await;
+    }
+}
+
+#[test]
+fn option_negotiation_error_code() {
+    let error = packet::Error::OptionNegotiation;
+    assert_eq!(error.code(), 8);
+    assert!(matches!(
+        packet::Error::from_code(8, Some("Option negotiation failed")),
+        packet::Error::OptionNegotiation
+    ));
+
+    let packet = Packet::decode(b"\x00\x05\x00\x08Invalid options\x00");
+    assert!(matches!(
+        packet,
+        Ok(Packet::Error(packet::Error::OptionNegotiation))
+    ));
+}
+
+#[test]
+fn min_block_size() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder.min_block_size(512).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Rrq(req_opts(block_size(16)))).await;
+        assert!(is_option_error(&client.recv().await));
+
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Wrq(req_opts(block_size(16)))).await;
+        assert!(is_option_error(&client.recv().await));
+
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Rrq(req_opts(block_size(1024)))).await;
+        assert!(is_oack(&client.recv().await));
+
+        // Default block size is not checked
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Rrq(req())).await;
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+    }));
+}
+
+#[test]
+fn min_block_size_ignored() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let builder = builder.min_block_size(512).ignore_client_block_size();
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        // Block size is not negotiated, so it can not be too small
+        client.send(Packet::Rrq(req_opts(block_size(16)))).await;
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+    }));
+}
+
+#[test]
+fn max_write_size() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder.max_write_size(1000).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Wrq(req_opts(transfer_size(1001)))).await;
+        assert!(is_option_error(&client.recv().await));
+
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Wrq(req_opts(transfer_size(1000)))).await;
+        assert!(is_oack(&client.recv().await));
+
+        // Read requests are not limited
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Rrq(req_opts(transfer_size(0)))).await;
+        assert!(is_oack(&client.recv().await));
+    }));
+}
+
+#[test]
+fn handler_rejects_options() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let builder = TftpServerBuilder::with_handler(NoWindowHandler)
+            .bind("127.0.0.1:0".parse().unwrap());
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        let mut req = req();
+        req.opts.window_size = Some(4);
+        client.send(Packet::Rrq(req)).await;
+        assert!(is_option_error(&client.recv().await));
+    }));
+}
+
+#[test]
+fn client_rejects_oack() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd =
+            builder.timeout(Duration::from_millis(100)).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let handle = tftpd.handle();
+        let _server = ex.spawn(tftpd.serve());
+
+        for wrq in [false, true] {
+            let mut client = RawClient::new(addr);
+
+            if wrq {
+                client.send(Packet::Wrq(req_opts(transfer_size(100)))).await;
+            } else {
+                client.send(Packet::Rrq(req_opts(transfer_size(0)))).await;
+            }
+
+            assert!(is_oack(&client.recv().await));
+            client.send(Packet::Error(packet::Error::OptionNegotiation)).await;
+
+            // Transfer ends without retransmissions or an error reply
+            let reply = client.try_recv(Duration::from_millis(300)).await;
+            assert!(reply.is_none(), "{:?}", reply);
+        }
+
+        wait_failed(&handle, 2).await;
+        let stats = handle.stats();
+        assert_eq!(stats.failed_transfers, 2);
+        assert_eq!(stats.retransmissions, 0);
+        assert!(stats.errors.is_empty());
+    }));
+}
@@ -0,0 +1,204 @@
+use async_channel::Sender;
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use futures_lite::io::Cursor;
+use futures_lite::AsyncWrite;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use super::raw_client::{req, RawClient};
+use crate::packet::{self, Packet};
+use crate::server::{
+    Handler, RequestContext, TftpServerBuilder, TransferOutcome,
+};
+
+/// Handler that reports the outcome of the transfers.
+struct OutcomeHandler {
+    data: Vec<u8>,
+    fail_close: bool,
+    // Bytes and success of each transfer
+    outcome_tx: Sender<(u64, bool)>,
+}
+
+/// Writer that can fail to close.
+struct CloseWriter {
+    fail_close: bool,
+}
+
+impl AsyncWrite for CloseWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+    ) -> Poll<io::Result<()>> {
+        if self.fail_close {
+            Poll::Ready(Err(io::ErrorKind::WriteZero.into()))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+#[crate::async_trait]
+impl Handler for OutcomeHandler {
+    type Reader = Cursor<Vec<u8>>;
+    type Writer = CloseWriter;
+
+    async fn read_req_open(
+        &mut self,
+        _ctx: &RequestContext,
+        _path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        let len = self.data.len() as u64;
+        Ok((Cursor::new(self.data.clone()), Some(len)))
+    }
+
+    async fn write_req_open(
+        &mut self,
+        _ctx: &RequestContext,
+        _path: &Path,
+        _size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error> {
+        Ok(CloseWriter {
+            fail_close: self.fail_close,
+        })
+    }
+
+    async fn read_req_finished(
+        &mut self,
+        _ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    ) {
+        let outcome = (outcome.bytes(), outcome.is_success());
+        self.outcome_tx.send(outcome).await.unwrap();
+    }
+
+    async fn write_req_finished(
+        &mut self,
+        ctx: &RequestContext,
+        outcome: &TransferOutcome,
+    ) {
+        self.read_req_finished(ctx, outcome).await;
+    }
+}
+
+#[test]
+fn rrq_finished() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (outcome_tx, outcome_rx) = async_channel::unbounded();
+        let handler = OutcomeHandler {
+            data: vec![1u8; 600],
+            fail_close: false,
+            outcome_tx,
+        };
+
+        let tftpd = TftpServerBuilder::with_handler(handler)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .timeout(Duration::from_millis(100))
+            .max_send_retries(1)
+            .build()
+            .await
+            .unwrap();
+        let listen_addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Successful transfer
+        let mut client = RawClient::new(listen_addr);
+        client.send(Packet::Rrq(req())).await;
+
+        for block_id in 1..=2 {
+            let packet = client.recv().await;
+            assert!(matches!(Packet::decode(&packet),
+                             Ok(Packet::Data(id, _)) if id == block_id));
+            client.send(Packet::Ack(block_id)).await;
+        }
+
+        assert_eq!(outcome_rx.recv().await.unwrap(), (600, true));
+
+        // Client stops acknowledging after the first block
+        let mut client = RawClient::new(listen_addr);
+        client.send(Packet::Rrq(req())).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+        client.send(Packet::Ack(1)).await;
+
+        assert_eq!(outcome_rx.recv().await.unwrap(), (512, false));
+    }));
+}
+
+fn wrq_finished(fail_close: bool) {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (outcome_tx, outcome_rx) = async_channel::unbounded();
+        let handler = OutcomeHandler {
+            data: Vec::new(),
+            fail_close,
+            outcome_tx,
+        };
+
+        let tftpd = TftpServerBuilder::with_handler(handler)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let mut client = RawClient::new(tftpd.listen_addr().unwrap());
+        let _server = ex.spawn(tftpd.serve());
+
+        client.send(Packet::Wrq(req())).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(0))));
+
+        client.send(Packet::Data(1, &[1u8; 512])).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(1))));
+
+        client.send(Packet::Data(2, &[1u8; 88])).await;
+
+        // Last block is acknowledged only if writer is closed
+        let packet = client.recv().await;
+
+        if fail_close {
+            assert!(matches!(
+                Packet::decode(&packet),
+                Ok(Packet::Error(packet::Error::DiskFull))
+            ));
+        } else {
+            assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(2))));
+        }
+
+        assert_eq!(outcome_rx.recv().await.unwrap(), (600, !fail_close));
+    }));
+}
+
+#[test]
+fn wrq_finished_success() {
+    wrq_finished(false);
+}
+
+#[test]
+fn wrq_finished_close_failure() {
+    wrq_finished(true);
+}
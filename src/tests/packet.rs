@@ -1,4 +1,7 @@
+#![allow(clippy::octal_escapes)]
+
 use bytes::{Bytes, BytesMut};
+use std::time::Duration;
 
 use crate::error::Error;
 use crate::packet::{self, Mode, Opts, Packet, RwReq};
@@ -16,7 +19,7 @@ fn check_rrq() {
 
     assert!(matches!(packet, Ok(Packet::Rrq(ref req))
                     if req == &RwReq {
-                        filename: "abc".to_string(),
+                        filename: b"abc".to_vec(),
                         mode: Mode::Netascii,
                         opts: Opts::default()
                     }
@@ -31,7 +34,7 @@ fn check_rrq() {
 
     assert!(matches!(packet, Ok(Packet::Rrq(ref req))
                     if req == &RwReq {
-                        filename: "abc".to_string(),
+                        filename: b"abc".to_vec(),
                         mode: Mode::Netascii,
                         opts: Opts::default()
                     }
@@ -49,7 +52,9 @@ fn check_rrq() {
     assert!(matches!(packet, Err(ref e) if matches!(e, Error::InvalidPacket)));
 
     let packet = Packet::decode(b"\x00\x01abc\0netascXX\0");
-    assert!(matches!(packet, Err(ref e) if matches!(e, Error::InvalidPacket)));
+    assert!(
+        matches!(packet, Err(Error::UnknownMode(ref m)) if m == "netascXX")
+    );
 
     let packet = Packet::decode(
         b"\x00\x01abc\0netascii\0blksize\0123\0timeout\03\0tsize\05556\0",
@@ -57,12 +62,14 @@ fn check_rrq() {
 
     assert!(matches!(packet, Ok(Packet::Rrq(ref req))
                     if req == &RwReq {
-                        filename: "abc".to_string(),
+                        filename: b"abc".to_vec(),
                         mode: Mode::Netascii,
                         opts: Opts {
                             block_size: Some(123),
                             timeout: Some(3),
-                            transfer_size: Some(5556)
+                            timeout_us: None,
+                            transfer_size: Some(5556),
+                            window_size: None,
                         }
                     }
     ));
@@ -80,7 +87,7 @@ fn check_rrq() {
     let packet = Packet::decode(b"\x00\x01abc\0netascii\0blksizeX\0123\0");
     assert!(matches!(packet, Ok(Packet::Rrq(ref req))
                     if req == &RwReq {
-                        filename: "abc".to_string(),
+                        filename: b"abc".to_vec(),
                         mode: Mode::Netascii,
                         opts: Opts::default()
                     }
@@ -93,7 +100,7 @@ fn check_wrq() {
 
     assert!(matches!(packet, Ok(Packet::Wrq(ref req))
                     if req == &RwReq {
-                        filename: "abc".to_string(),
+                        filename: b"abc".to_vec(),
                         mode: Mode::Octet,
                         opts: Opts::default()
                     }
@@ -105,7 +112,7 @@ fn check_wrq() {
 
     assert!(matches!(packet, Ok(Packet::Wrq(ref req))
                     if req == &RwReq {
-                        filename: "abc".to_string(),
+                        filename: b"abc".to_vec(),
                         mode: Mode::Octet,
                         opts: Opts::default()
                     }
@@ -120,7 +127,7 @@ fn check_wrq() {
     assert!(matches!(packet, Err(ref e) if matches!(e, Error::InvalidPacket)));
 
     let packet = Packet::decode(b"\x00\x02abc\0octex\0");
-    assert!(matches!(packet, Err(ref e) if matches!(e, Error::InvalidPacket)));
+    assert!(matches!(packet, Err(Error::UnknownMode(ref m)) if m == "octex"));
 
     let packet = Packet::decode(
         b"\x00\x02abc\0octet\0blksize\0123\0timeout\03\0tsize\05556\0",
@@ -128,12 +135,14 @@ fn check_wrq() {
 
     assert!(matches!(packet, Ok(Packet::Wrq(ref req))
                     if req == &RwReq {
-                        filename: "abc".to_string(),
+                        filename: b"abc".to_vec(),
                         mode: Mode::Octet,
                         opts: Opts {
                             block_size: Some(123),
                             timeout: Some(3),
-                            transfer_size: Some(5556)
+                            timeout_us: None,
+                            transfer_size: Some(5556),
+                            window_size: None,
                         }
                     }
     ));
@@ -146,7 +155,7 @@ fn check_wrq() {
     let packet = Packet::decode(b"\x00\x02abc\0octet\0blksizeX\0123\0");
     assert!(matches!(packet, Ok(Packet::Wrq(ref req))
                     if req == &RwReq {
-                        filename: "abc".to_string(),
+                        filename: b"abc".to_vec(),
                         mode: Mode::Octet,
                         opts: Opts::default()
                     }
@@ -156,14 +165,12 @@ fn check_wrq() {
 #[test]
 fn check_data() {
     let packet = Packet::decode(b"\x00\x03\x00\x09abcde");
-    assert!(
-        matches!(packet, Ok(Packet::Data(9, ref data)) if &data[..] == b"abcde")
-    );
+    assert!(matches!(packet, Ok(Packet::Data(9, data)) if data == b"abcde"));
 
     assert_eq!(packet_to_bytes(&packet.unwrap()), b"\x00\x03\x00\x09abcde"[..]);
 
     let packet = Packet::decode(b"\x00\x03\x00\x09");
-    assert!(matches!(packet, Ok(Packet::Data(9, ref data)) if data.is_empty()));
+    assert!(matches!(packet, Ok(Packet::Data(9, data)) if data.is_empty()));
     assert_eq!(packet_to_bytes(&packet.unwrap()), b"\x00\x03\x00\x09"[..]);
 }
 
@@ -222,7 +229,9 @@ fn check_oack() {
                     if opts == &Opts {
                         block_size: Some(123),
                         timeout: None,
-                        transfer_size: None
+                        timeout_us: None,
+                        transfer_size: None,
+                        window_size: None,
                     }
     ));
 
@@ -231,7 +240,9 @@ fn check_oack() {
                     if opts == &Opts {
                         block_size: None,
                         timeout: Some(3),
-                        transfer_size: None
+                        timeout_us: None,
+                        transfer_size: None,
+                        window_size: None,
                     }
     ));
 
@@ -240,17 +251,37 @@ fn check_oack() {
                     if opts == &Opts {
                         block_size: None,
                         timeout: None,
+                        timeout_us: None,
                         transfer_size: Some(5556),
+                        window_size: None,
                     }
     ));
 
+    let packet = Packet::decode(b"\x00\x06windowsize\016\0");
+    assert!(matches!(packet, Ok(Packet::OAck(ref opts))
+                    if opts == &Opts {
+                        block_size: None,
+                        timeout: None,
+                        timeout_us: None,
+                        transfer_size: None,
+                        window_size: Some(16),
+                    }
+    ));
+
+    assert_eq!(
+        packet_to_bytes(&packet.unwrap()),
+        b"\x00\x06windowsize\016\0"[..]
+    );
+
     let packet =
         Packet::decode(b"\x00\x06tsize\05556\0blksize\0123\0timeout\03\0");
     assert!(matches!(packet, Ok(Packet::OAck(ref opts))
                     if opts == &Opts {
                         block_size: Some(123),
                         timeout: Some(3),
+                        timeout_us: None,
                         transfer_size: Some(5556),
+                        window_size: None,
                     }
     ));
 }
@@ -341,3 +372,86 @@ fn check_timeout_boundaries() {
         }
     );
 }
+
+#[test]
+fn check_utimeout_boundaries() {
+    let (_, opts) = parse_opts(b"utimeout\09999\0").unwrap();
+    assert_eq!(opts, Opts::default());
+
+    let (_, opts) = parse_opts(b"utimeout\010000\0").unwrap();
+    assert_eq!(
+        opts,
+        Opts {
+            timeout_us: Some(10000),
+            ..Opts::default()
+        }
+    );
+
+    let (_, opts) = parse_opts(b"utimeout\0255000000\0").unwrap();
+    assert_eq!(
+        opts,
+        Opts {
+            timeout_us: Some(255_000_000),
+            ..Opts::default()
+        }
+    );
+
+    let (_, opts) = parse_opts(b"utimeout\0255000001\0").unwrap();
+    assert_eq!(opts, Opts::default());
+
+    let (_, opts) = parse_opts(b"UTIMEOUT\0500000\0timeout\02\0").unwrap();
+    assert_eq!(
+        opts,
+        Opts {
+            timeout: Some(2),
+            timeout_us: Some(500_000),
+            ..Opts::default()
+        }
+    );
+    assert_eq!(opts.timeout_duration(), Some(Duration::from_millis(500)));
+
+    let packet = Packet::OAck(Opts {
+        timeout_us: Some(500_000),
+        ..Opts::default()
+    });
+    assert_eq!(packet_to_bytes(&packet), b"\x00\x06utimeout\0500000\0"[..]);
+}
+
+#[test]
+fn check_windowsize_boundaries() {
+    let (_, opts) = parse_opts(b"windowsize\00\0").unwrap();
+    assert_eq!(
+        opts,
+        Opts {
+            window_size: None,
+            ..Opts::default()
+        }
+    );
+
+    let (_, opts) = parse_opts(b"windowsize\01\0").unwrap();
+    assert_eq!(
+        opts,
+        Opts {
+            window_size: Some(1),
+            ..Opts::default()
+        }
+    );
+
+    let (_, opts) = parse_opts(b"windowsize\065535\0").unwrap();
+    assert_eq!(
+        opts,
+        Opts {
+            window_size: Some(65535),
+            ..Opts::default()
+        }
+    );
+
+    let (_, opts) = parse_opts(b"windowsize\065536\0").unwrap();
+    assert_eq!(
+        opts,
+        Opts {
+            window_size: None,
+            ..Opts::default()
+        }
+    );
+}
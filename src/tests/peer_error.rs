@@ -0,0 +1,112 @@
+use async_channel::Receiver;
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{req, start_server, RawClient};
+use crate::packet::{self, Packet};
+use crate::server::{RequestContext, TransferEvent};
+use crate::Error;
+
+/// Observer that reports the code and message of peer errors that
+/// transfers failed with.
+fn peer_errors() -> (
+    impl Fn(&RequestContext, &TransferEvent) + Send + Sync,
+    Receiver<(u16, String)>,
+) {
+    let (tx, rx) = async_channel::unbounded();
+
+    let observer = move |_: &RequestContext, event: &TransferEvent| {
+        if let TransferEvent::Failed {
+            error,
+            ..
+        } = event
+        {
+            match error {
+                Error::PeerError(e, msg) => {
+                    tx.try_send((e.code(), msg.clone())).unwrap()
+                }
+                e => panic!("Unexpected error: {}", e),
+            }
+        }
+    };
+
+    (observer, rx)
+}
+
+#[test]
+fn peer_error_rrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 2000]);
+        let (observer, errors) = peer_errors();
+        let builder =
+            builder.timeout(Duration::from_millis(100)).observer(observer);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req())).await;
+        client.recv().await;
+        client.send(Packet::Ack(1)).await;
+        client.recv().await;
+
+        let error = packet::Error::Msg("Aborted by user".to_string());
+        client.send(Packet::Error(error)).await;
+
+        assert_eq!(errors.recv().await.unwrap(), (0, "Aborted by user".into()));
+
+        // Server neither retransmits nor replies to the error
+        let reply = client.try_recv(Duration::from_millis(300)).await;
+        assert!(reply.is_none(), "{:?}", reply);
+    }));
+}
+
+#[test]
+fn peer_error_wrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, written_rx) = mem_server(Vec::new());
+        let (observer, errors) = peer_errors();
+        let builder =
+            builder.timeout(Duration::from_millis(100)).observer(observer);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Wrq(req())).await;
+        client.recv().await;
+        client.send(Packet::Data(1, &[7u8; 512])).await;
+        client.recv().await;
+
+        // Message of a known error code is kept
+        client.send_raw_to_peer(b"\x00\x05\x00\x03No space left\x00").await;
+
+        assert_eq!(errors.recv().await.unwrap(), (3, "No space left".into()));
+        assert_eq!(written_rx.recv().await.unwrap().len(), 512);
+
+        let reply = client.try_recv(Duration::from_millis(300)).await;
+        assert!(reply.is_none(), "{:?}", reply);
+    }));
+}
+
+#[test]
+fn peer_error_of_other_peer() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let builder = builder.timeout(Duration::from_millis(100));
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req())).await;
+        client.recv().await;
+
+        // Error of another peer does not abort the transfer
+        let other = RawClient::new(client.peer().unwrap());
+        other.send(Packet::Error(packet::Error::UnknownError)).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+        client.send(Packet::Ack(1)).await;
+    }));
+}
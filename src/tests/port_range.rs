@@ -0,0 +1,161 @@
+use async_executor::Executor;
+use async_io::Timer;
+use futures_lite::future::block_on;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{rrq, start_server, RawClient};
+use crate::packet::{self, Mode, Opts, Packet, RwReq};
+
+/// Port that OS considers free.
+fn free_port() -> u16 {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.local_addr().unwrap().port()
+}
+
+#[test]
+fn port_range_transfer() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let port = free_port();
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder.port_range(port..=port).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Port is free again after the first transfer
+        for _ in 0..2 {
+            let mut client = RawClient::new(addr);
+            client.send(rrq()).await;
+
+            let packet = client.recv().await;
+            assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+            assert_eq!(client.peer().unwrap().port(), port);
+
+            client.send(Packet::Ack(1)).await;
+
+            // Let the transfer release its socket
+            Timer::after(Duration::from_millis(100)).await;
+        }
+    }));
+}
+
+#[test]
+fn port_range_exhausted() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let taken = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder.port_range(port..=port).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let mut client = RawClient::new(addr);
+        let _server = ex.spawn(tftpd.serve());
+
+        client.send(rrq()).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(
+            Packet::decode(&packet),
+            Ok(Packet::Error(packet::Error::Msg(_)))
+        ));
+
+        // Error is sent from the listening port
+        assert_eq!(client.peer().unwrap(), addr);
+    }));
+}
+
+#[test]
+fn port_range_exhausted_closed_peer() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let taken = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let builder = builder.port_range(port..=port);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        // Peer is gone before the error reaches it
+        let closed = UdpSocket::bind("127.0.0.1:0").unwrap();
+        closed.send_to(&rrq().to_bytes(), client.server()).unwrap();
+        drop(closed);
+
+        Timer::after(Duration::from_millis(100)).await;
+
+        // Listener that sent the error keeps serving
+        client.send(rrq()).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Error(_))));
+        assert_eq!(client.peer().unwrap(), client.server());
+    }));
+}
+
+#[test]
+fn port_range_rejected_request() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let port = free_port();
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let builder = builder.port_range(port..=port);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client
+            .send(Packet::Rrq(RwReq {
+                filename: b"test".to_vec(),
+                mode: Mode::Mail,
+                opts: Opts::default(),
+            }))
+            .await;
+
+        // Error is sent from a port of the range
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Error(_))));
+        assert_eq!(client.peer().unwrap().port(), port);
+    }));
+}
+
+#[test]
+fn port_range_cycle() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let first = free_port();
+        let last = loop {
+            match free_port() {
+                port if port > first => break port,
+                _ => continue,
+            }
+        };
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder.port_range(first..=last).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Concurrent transfers get different ports of the range
+        let mut client1 = RawClient::new(addr);
+        let mut client2 = RawClient::new(addr);
+
+        client1.send(rrq()).await;
+        client1.recv().await;
+        client2.send(rrq()).await;
+        client2.recv().await;
+
+        let port1 = client1.peer().unwrap().port();
+        let port2 = client2.peer().unwrap().port();
+        assert_ne!(port1, port2);
+        assert!((first..=last).contains(&port1));
+        assert!((first..=last).contains(&port2));
+
+        client1.send(Packet::Ack(1)).await;
+        client2.send(Packet::Ack(1)).await;
+    }));
+}
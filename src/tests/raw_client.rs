@@ -0,0 +1,116 @@
+use async_executor::{Executor, Task};
+use async_io::Async;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::packet::{Mode, Opts, Packet, RwReq};
+use crate::server::{Handler, TftpServerBuilder};
+use crate::utils::io_timeout;
+
+/// Octet request of `test` file with `opts`.
+pub fn req_opts(opts: Opts) -> RwReq {
+    RwReq {
+        filename: b"test".to_vec(),
+        mode: Mode::Octet,
+        opts,
+    }
+}
+
+/// Octet request of `test` file without options.
+pub fn req() -> RwReq {
+    req_opts(Opts::default())
+}
+
+/// Read request of `test` file without options.
+pub fn rrq() -> Packet<'static> {
+    Packet::Rrq(req())
+}
+
+/// Build the server of `builder` and run it on `ex`.
+///
+/// Returns the task of the server and a client for it.
+pub async fn start_server<H>(
+    ex: &Executor<'_>,
+    builder: TftpServerBuilder<H>,
+) -> (Task<Result<()>>, RawClient)
+where
+    H: Handler + 'static,
+{
+    let tftpd = builder.build().await.unwrap();
+    let client = RawClient::new(tftpd.listen_addr().unwrap());
+
+    (ex.spawn(tftpd.serve()), client)
+}
+
+/// Client that sends and receives raw packets, for testing the protocol
+/// behavior of the server.
+pub struct RawClient {
+    socket: Async<UdpSocket>,
+    server: SocketAddr,
+    peer: Option<SocketAddr>,
+}
+
+impl RawClient {
+    pub fn new(server: SocketAddr) -> Self {
+        let socket = Async::<UdpSocket>::bind(([127, 0, 0, 1], 0)).unwrap();
+
+        RawClient {
+            socket,
+            server,
+            peer: None,
+        }
+    }
+
+    /// Listening address of the server.
+    pub fn server(&self) -> SocketAddr {
+        self.server
+    }
+
+    /// Local address of the client.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.socket.get_ref().local_addr().unwrap()
+    }
+
+    /// Address the server replied from.
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    /// Send packet to the transfer ID of the server, or to the listening
+    /// address if server did not reply yet.
+    pub async fn send(&self, packet: Packet<'_>) {
+        let dest = self.peer.unwrap_or(self.server);
+        self.socket.send_to(&packet.to_bytes()[..], dest).await.unwrap();
+    }
+
+    /// Send raw bytes to the listening address of the server.
+    pub async fn send_raw(&self, data: &[u8]) {
+        self.socket.send_to(data, self.server).await.unwrap();
+    }
+
+    /// Send raw bytes to the transfer ID of the server.
+    pub async fn send_raw_to_peer(&self, data: &[u8]) {
+        let dest = self.peer.expect("server did not reply yet");
+        self.socket.send_to(data, dest).await.unwrap();
+    }
+
+    /// Receive the next packet from the server.
+    pub async fn recv(&mut self) -> Vec<u8> {
+        self.try_recv(Duration::from_secs(5)).await.expect("recv timed out")
+    }
+
+    /// Receive the next packet from the server, if any arrives within
+    /// `timeout`.
+    pub async fn try_recv(&mut self, timeout: Duration) -> Option<Vec<u8>> {
+        let mut buf = vec![0u8; 65536];
+
+        let (len, peer) =
+            io_timeout(timeout, self.socket.recv_from(&mut buf)).await.ok()?;
+
+        assert_eq!(*self.peer.get_or_insert(peer), peer);
+        buf.truncate(len);
+
+        Some(buf)
+    }
+}
@@ -0,0 +1,89 @@
+use async_channel::{Receiver, Sender};
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use futures_lite::io::{Cursor, Sink};
+use std::path::Path;
+
+use super::raw_client::RawClient;
+use crate::packet::{self, Mode, Opts, Packet, RwReq};
+use crate::server::{RequestContext, SharedHandler, TftpServerBuilder};
+
+/// Handler that does not finish opening `slow` until `fast` is opened.
+struct BlockingHandler {
+    fast_tx: Sender<()>,
+    fast_rx: Receiver<()>,
+}
+
+#[crate::async_trait]
+impl SharedHandler for BlockingHandler {
+    type Reader = Cursor<Vec<u8>>;
+    type Writer = Sink;
+
+    async fn read_req_open(
+        &self,
+        ctx: &RequestContext,
+        _path: &Path,
+    ) -> Result<(Self::Reader, Option<u64>), packet::Error> {
+        match ctx.filename() {
+            "slow" => self.fast_rx.recv().await.unwrap(),
+            _ => self.fast_tx.send(()).await.unwrap(),
+        }
+
+        let data = ctx.filename().as_bytes().to_vec();
+        let len = data.len() as u64;
+        Ok((Cursor::new(data), Some(len)))
+    }
+
+    async fn write_req_open(
+        &self,
+        _ctx: &RequestContext,
+        _path: &Path,
+        _size: Option<u64>,
+    ) -> Result<Self::Writer, packet::Error> {
+        Err(packet::Error::IllegalOperation)
+    }
+}
+
+fn rrq(filename: &str) -> Packet<'static> {
+    Packet::Rrq(RwReq {
+        filename: filename.as_bytes().to_vec(),
+        mode: Mode::Octet,
+        opts: Opts::default(),
+    })
+}
+
+#[test]
+fn concurrent_opens() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (fast_tx, fast_rx) = async_channel::bounded(1);
+        let handler = BlockingHandler {
+            fast_tx,
+            fast_rx,
+        };
+
+        let tftpd = TftpServerBuilder::with_shared_handler(handler)
+            .bind("127.0.0.1:0".parse().unwrap())
+            .build()
+            .await
+            .unwrap();
+        let mut slow_client = RawClient::new(tftpd.listen_addr().unwrap());
+        let mut fast_client = RawClient::new(tftpd.listen_addr().unwrap());
+        let _server = ex.spawn(tftpd.serve());
+
+        slow_client.send(rrq("slow")).await;
+        fast_client.send(rrq("fast")).await;
+
+        // With exclusive handlers `fast` would wait for `slow` forever.
+        let packet = fast_client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(1, d)) if d == b"fast"));
+        fast_client.send(Packet::Ack(1)).await;
+
+        let packet = slow_client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(1, d)) if d == b"slow"));
+        slow_client.send(Packet::Ack(1)).await;
+    }));
+}
@@ -0,0 +1,118 @@
+use async_executor::Executor;
+use async_io::Timer;
+use futures_lite::future::{self, block_on};
+use std::future::Future;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{rrq, RawClient};
+use crate::packet::{self, Packet};
+
+async fn stopped<T>(server: impl Future<Output = T>) -> T {
+    future::or(server, async {
+        Timer::after(Duration::from_secs(5)).await;
+        panic!("server did not stop");
+    })
+    .await
+}
+
+#[test]
+fn shutdown_idle() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(Vec::new());
+
+        let tftpd = builder.build().await.unwrap();
+        let handle = tftpd.handle();
+        let server = ex.spawn(tftpd.serve());
+
+        handle.shutdown(Duration::from_secs(5));
+        stopped(server).await.unwrap();
+    }));
+}
+
+fn graceful(single_port: bool) {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = vec![7u8; 600];
+        let (mut builder, _written_rx) = mem_server(data.clone());
+
+        if single_port {
+            builder = builder.single_port();
+        }
+        let tftpd = builder.build().await.unwrap();
+        let listen_addr = tftpd.listen_addr().unwrap();
+        let handle = tftpd.handle();
+        let mut server = ex.spawn(tftpd.serve());
+        let mut client = RawClient::new(listen_addr);
+
+        client.send(rrq()).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(1, d)) if d == &data[..512]));
+
+        handle.shutdown(Duration::from_secs(5));
+
+        // New requests are not served
+        let mut new_client = RawClient::new(listen_addr);
+        new_client.send(rrq()).await;
+        assert!(new_client
+            .try_recv(Duration::from_millis(200))
+            .await
+            .is_none());
+
+        // Server waits for the transfer in progress
+        assert!(future::poll_once(&mut server).await.is_none());
+
+        client.send(Packet::Ack(1)).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(2, d)) if d == &data[512..]));
+        client.send(Packet::Ack(2)).await;
+
+        stopped(server).await.unwrap();
+    }));
+}
+
+#[test]
+fn shutdown_graceful() {
+    graceful(false);
+}
+
+#[test]
+fn shutdown_graceful_single_port() {
+    graceful(true);
+}
+
+#[test]
+fn shutdown_cancel() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 600]);
+
+        let tftpd = builder.build().await.unwrap();
+        let mut client = RawClient::new(tftpd.listen_addr().unwrap());
+        let handle = tftpd.handle();
+        let server = ex.spawn(tftpd.serve());
+
+        client.send(rrq()).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+
+        handle.shutdown(Duration::from_millis(100));
+
+        // Transfer is cancelled with an error from its transfer ID
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Error(packet::Error::Msg(m)))
+                            if m == "Server is shutting down"));
+
+        stopped(server).await.unwrap();
+    }));
+}
@@ -0,0 +1,124 @@
+use async_executor::Executor;
+use async_io::Timer;
+use futures_lite::future::block_on;
+use std::net::UdpSocket;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{rrq, start_server, RawClient};
+use crate::client::TftpClientBuilder;
+use crate::packet::Packet;
+
+#[test]
+fn single_port_rrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = vec![7u8; 600];
+        let (builder, _written_rx) = mem_server(data.clone());
+        let (_server, mut client) =
+            start_server(&ex, builder.single_port()).await;
+
+        client.send(rrq()).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(1, d)) if d == &data[..512]));
+        client.send(Packet::Ack(1)).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(2, d)) if d == &data[512..]));
+        client.send(Packet::Ack(2)).await;
+
+        // Client would reject packets of any other address
+        assert_eq!(client.peer(), Some(client.server()));
+    }));
+}
+
+#[test]
+fn single_port_closed_peer() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = vec![7u8; 600];
+        let (builder, _written_rx) = mem_server(data.clone());
+        let builder = builder.single_port().timeout(Duration::from_millis(50));
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        // Listener sends and retransmits Data to a peer that is gone
+        let closed = UdpSocket::bind("127.0.0.1:0").unwrap();
+        closed.send_to(&rrq().to_bytes(), client.server()).unwrap();
+        drop(closed);
+
+        Timer::after(Duration::from_millis(200)).await;
+
+        client.send(rrq()).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(1, d)) if d == &data[..512]));
+    }));
+}
+
+#[test]
+fn single_port_concurrent() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = vec![7u8; 600];
+        let (builder, _written_rx) = mem_server(data.clone());
+        let (_server, mut client1) =
+            start_server(&ex, builder.single_port()).await;
+        let mut client2 = RawClient::new(client1.server());
+
+        client1.send(rrq()).await;
+        let packet = client1.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+
+        client2.send(rrq()).await;
+        let packet = client2.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+
+        // Each ACK reaches only the transfer of its peer
+        client2.send(Packet::Ack(1)).await;
+        let packet = client2.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(2, d)) if d == &data[512..]));
+
+        client1.send(Packet::Ack(1)).await;
+        let packet = client1.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(2, d)) if d == &data[512..]));
+
+        client1.send(Packet::Ack(2)).await;
+        client2.send(Packet::Ack(2)).await;
+    }));
+}
+
+#[test]
+fn single_port_client_transfer() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = vec![7u8; 5000];
+        let (builder, written_rx) = mem_server(data.clone());
+        let tftpd = builder.single_port().build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().block_size(1024).build();
+
+        // download
+        let mut received = Vec::new();
+        let len = client.get(addr, "test", &mut received).await.unwrap();
+        assert_eq!(len, data.len() as u64);
+        assert_eq!(received, data);
+
+        // upload
+        let size = Some(data.len() as u64);
+        let len = client.put(addr, "test", &mut &data[..], size).await.unwrap();
+        assert_eq!(len, data.len() as u64);
+        assert_eq!(written_rx.recv().await.unwrap(), data);
+    }));
+}
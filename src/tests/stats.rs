@@ -0,0 +1,126 @@
+use async_executor::Executor;
+use async_io::Timer;
+use futures_lite::future::block_on;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{req, RawClient};
+use crate::packet::Packet;
+use crate::server::{Acl, ServerHandle, ServerStats};
+
+/// Wait until statistics of the server satisfy `done`.
+async fn wait_stats<F>(handle: &ServerHandle, done: F) -> ServerStats
+where
+    F: Fn(&ServerStats) -> bool,
+{
+    for _ in 0..100 {
+        let stats = handle.stats();
+
+        if done(&stats) {
+            return stats;
+        }
+
+        Timer::after(Duration::from_millis(20)).await;
+    }
+
+    panic!("Statistics were not updated: {:?}", handle.stats());
+}
+
+#[test]
+fn stats_transfers() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, written_rx) = mem_server(vec![7u8; 600]);
+
+        let tftpd = builder.build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let handle = tftpd.handle();
+        let _server = ex.spawn(tftpd.serve());
+
+        let stats = handle.stats();
+        assert_eq!(stats.active_transfers, 0);
+        assert_eq!(stats.transfer_durations.count(), 0);
+
+        // Read request
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Rrq(req())).await;
+        client.recv().await;
+        client.send(Packet::Ack(1)).await;
+        client.recv().await;
+        client.send(Packet::Ack(2)).await;
+
+        // Write request
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Wrq(req())).await;
+        client.recv().await;
+        client.send(Packet::Data(1, &[7u8; 512])).await;
+        client.recv().await;
+        client.send(Packet::Data(2, b"abc")).await;
+        client.recv().await;
+        written_rx.recv().await.unwrap();
+
+        let stats = wait_stats(&handle, |s| s.completed_transfers == 2).await;
+        assert_eq!(stats.active_transfers, 0);
+        assert_eq!(stats.failed_transfers, 0);
+        assert_eq!(stats.bytes_sent, 600);
+        assert_eq!(stats.bytes_received, 515);
+        assert_eq!(stats.retransmissions, 0);
+        assert_eq!(stats.timeouts, 0);
+        assert!(stats.errors.is_empty());
+
+        let durations = &stats.transfer_durations;
+        assert_eq!(durations.count(), 2);
+        assert_eq!(durations.buckets().last().unwrap().1, 2);
+        assert!(durations
+            .buckets()
+            .windows(2)
+            .all(|w| w[0].0 < w[1].0 && w[0].1 <= w[1].1));
+    }));
+}
+
+#[test]
+fn stats_errors() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+
+        let tftpd = builder
+            .timeout(Duration::from_millis(100))
+            .max_send_retries(1)
+            .wrq_acl(Acl::new().deny("127.0.0.1".parse().unwrap()))
+            .build()
+            .await
+            .unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let handle = tftpd.handle();
+        let _server = ex.spawn(tftpd.serve());
+
+        // Write request is denied
+        let mut client = RawClient::new(addr);
+        client.send(Packet::Wrq(req())).await;
+        client.recv().await;
+
+        // Client never acknowledges the data
+        let client = RawClient::new(addr);
+        client.send(Packet::Rrq(req())).await;
+
+        let stats = wait_stats(&handle, |s| s.active_transfers == 1).await;
+        assert_eq!(stats.completed_transfers, 0);
+        assert_eq!(stats.failed_transfers, 0);
+
+        let stats = wait_stats(&handle, |s| s.failed_transfers == 1).await;
+        assert_eq!(stats.active_transfers, 0);
+        assert_eq!(stats.completed_transfers, 0);
+        assert_eq!(stats.bytes_sent, 200);
+        assert_eq!(stats.retransmissions, 1);
+        assert_eq!(stats.timeouts, 2);
+        assert_eq!(stats.transfer_durations.count(), 1);
+
+        // Permission denied and the message of the failed transfer
+        assert_eq!(stats.errors.get(&2), Some(&1));
+        assert_eq!(stats.errors.get(&0), Some(&1));
+        assert_eq!(stats.errors.len(), 2);
+    }));
+}
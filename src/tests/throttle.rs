@@ -0,0 +1,111 @@
+use async_executor::Executor;
+use futures_lite::future::{self, block_on};
+use std::time::{Duration, Instant};
+
+use super::mem_handler::mem_server;
+use crate::client::TftpClientBuilder;
+
+const RATE: u64 = 4000;
+
+#[test]
+fn throttle_rrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        // One second of burst and one second of waiting
+        let data = vec![7u8; 2 * RATE as usize];
+
+        let (builder, _written_rx) = mem_server(data.clone());
+        let tftpd =
+            builder.rate_limit_per_transfer(RATE).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().build();
+        let start = Instant::now();
+
+        let mut received = Vec::new();
+        client.get(addr, "test", &mut received).await.unwrap();
+
+        assert_eq!(received, data);
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }));
+}
+
+#[test]
+fn throttle_rrq_counts_data() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        // Fits in the burst, but with the headers of 8-byte blocks it
+        // would take 50% more
+        let data = vec![7u8; RATE as usize];
+
+        let (builder, _written_rx) = mem_server(data.clone());
+        let tftpd =
+            builder.rate_limit_per_transfer(RATE).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().block_size(8).build();
+        let start = Instant::now();
+
+        let mut received = Vec::new();
+        client.get(addr, "test", &mut received).await.unwrap();
+
+        assert_eq!(received, data);
+        assert!(start.elapsed() < Duration::from_millis(300));
+    }));
+}
+
+#[test]
+fn throttle_wrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let data = vec![7u8; 2 * RATE as usize];
+
+        let (builder, _written_rx) = mem_server(Vec::new());
+        let tftpd =
+            builder.rate_limit_per_transfer(RATE).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().build();
+        let start = Instant::now();
+
+        client.put(addr, "test", &mut &data[..], None).await.unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }));
+}
+
+#[test]
+fn throttle_total() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        // Each transfer alone fits in the burst, both together do not
+        let data = vec![7u8; RATE as usize];
+
+        let (builder, _written_rx) = mem_server(data.clone());
+        let tftpd = builder.rate_limit_total(RATE).build().await.unwrap();
+        let addr = tftpd.listen_addr().unwrap();
+        let _server = ex.spawn(tftpd.serve());
+
+        let client = TftpClientBuilder::new().build();
+        let start = Instant::now();
+
+        let get = || async {
+            let mut received = Vec::new();
+            client.get(addr, "test", &mut received).await.unwrap();
+            received
+        };
+
+        let (received1, received2) = future::zip(get(), get()).await;
+
+        assert_eq!(received1, data);
+        assert_eq!(received2, data);
+        assert!(start.elapsed() >= Duration::from_millis(900));
+    }));
+}
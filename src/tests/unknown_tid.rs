@@ -0,0 +1,105 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{req, start_server, RawClient};
+use crate::packet::{self, Packet};
+
+fn is_unknown_tid(packet: &[u8]) -> bool {
+    matches!(
+        Packet::decode(packet),
+        Ok(Packet::Error(packet::Error::UnknownTransferId))
+    )
+}
+
+#[test]
+fn unknown_tid_rrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 2000]);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req())).await;
+        client.recv().await;
+
+        let mut other = RawClient::new(client.peer().unwrap());
+        other.send(Packet::Ack(1)).await;
+        assert!(is_unknown_tid(&other.recv().await));
+
+        // Transfer continues with its client
+        client.send(Packet::Ack(1)).await;
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(2, _))));
+    }));
+}
+
+#[test]
+fn unknown_tid_wrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, written_rx) = mem_server(Vec::new());
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Wrq(req())).await;
+        client.recv().await;
+
+        let mut other = RawClient::new(client.peer().unwrap());
+        other.send(Packet::Data(1, &[1u8; 10])).await;
+        assert!(is_unknown_tid(&other.recv().await));
+
+        client.send(Packet::Data(1, &[7u8; 10])).await;
+        assert_eq!(client.recv().await, Packet::Ack(1).to_bytes());
+        assert_eq!(written_rx.recv().await.unwrap(), vec![7u8; 10]);
+    }));
+}
+
+#[test]
+fn unknown_tid_reply_limit() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 2000]);
+        let builder = builder.unknown_tid_reply_limit(2);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req())).await;
+        client.recv().await;
+
+        let mut other = RawClient::new(client.peer().unwrap());
+        for _ in 0..3 {
+            other.send(Packet::Ack(1)).await;
+        }
+
+        assert!(is_unknown_tid(&other.recv().await));
+        assert!(is_unknown_tid(&other.recv().await));
+        let reply = other.try_recv(Duration::from_millis(300)).await;
+        assert!(reply.is_none(), "{:?}", reply);
+    }));
+}
+
+#[test]
+fn unknown_tid_no_reply() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 2000]);
+        let builder = builder.unknown_tid_reply_limit(0);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req())).await;
+        client.recv().await;
+
+        let mut other = RawClient::new(client.peer().unwrap());
+        other.send(Packet::Ack(1)).await;
+
+        let reply = other.try_recv(Duration::from_millis(300)).await;
+        assert!(reply.is_none(), "{:?}", reply);
+
+        client.send(Packet::Ack(1)).await;
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(2, _))));
+    }));
+}
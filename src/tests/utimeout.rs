@@ -0,0 +1,74 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use std::time::{Duration, Instant};
+
+use super::mem_handler::mem_server;
+use super::raw_client::{req_opts, start_server};
+use crate::packet::{Opts, Packet, RwReq};
+
+fn req(timeout_us: u32) -> RwReq {
+    req_opts(Opts {
+        timeout_us: Some(timeout_us),
+        ..Opts::default()
+    })
+}
+
+#[test]
+fn utimeout_rrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req(100_000))).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::OAck(opts))
+                         if opts.timeout_us == Some(100_000)));
+        client.send(Packet::Ack(0)).await;
+
+        // Data is retransmitted after the negotiated 100ms, instead of the
+        // default 3 seconds.
+        let sent = Instant::now();
+        client.recv().await;
+        client.recv().await;
+        assert!(sent.elapsed() < Duration::from_secs(1));
+    }));
+}
+
+#[test]
+fn utimeout_wrq() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(Vec::new());
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Wrq(req(100_000))).await;
+
+        // OACK is sent again after the negotiated 100ms
+        let sent = Instant::now();
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::OAck(opts))
+                         if opts.timeout_us == Some(100_000)));
+        assert_eq!(client.recv().await, packet);
+        assert!(sent.elapsed() < Duration::from_secs(1));
+    }));
+}
+
+#[test]
+fn utimeout_ignored() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![7u8; 100]);
+        let builder = builder.ignore_client_timeout();
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        // Server does not acknowledge the option
+        client.send(Packet::Rrq(req(100_000))).await;
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Data(1, _))));
+    }));
+}
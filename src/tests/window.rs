@@ -0,0 +1,135 @@
+use async_executor::Executor;
+use futures_lite::future::block_on;
+use std::time::Duration;
+
+use super::mem_handler::mem_server;
+use super::raw_client::{req_opts, start_server};
+use crate::packet::{Opts, Packet, RwReq};
+
+fn req(window_size: u16) -> RwReq {
+    req_opts(Opts {
+        window_size: Some(window_size),
+        ..Opts::default()
+    })
+}
+
+fn block(data: &[u8], block_id: u16) -> &[u8] {
+    let start = (usize::from(block_id) - 1) * 512;
+    &data[start..std::cmp::min(start + 512, data.len())]
+}
+
+#[test]
+fn rrq_window() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        // 11 blocks, the last one is not full
+        let data: Vec<u8> = (0..10 * 512 + 100).map(|x| x as u8).collect();
+        let (builder, _written_rx) = mem_server(data.clone());
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req(4))).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::OAck(opts)) if opts.window_size == Some(4)));
+        client.send(Packet::Ack(0)).await;
+
+        // Whole window is sent without waiting for an ACK
+        for block_id in 1..=4 {
+            let packet = client.recv().await;
+            assert!(matches!(Packet::decode(&packet),
+                             Ok(Packet::Data(id, d))
+                                if id == block_id && d == block(&data, id)));
+        }
+
+        // Pretend that block 3 was lost, window restarts after block 2
+        client.send(Packet::Ack(2)).await;
+
+        for block_id in 3..=6 {
+            let packet = client.recv().await;
+            assert!(matches!(Packet::decode(&packet),
+                             Ok(Packet::Data(id, d))
+                                if id == block_id && d == block(&data, id)));
+        }
+
+        client.send(Packet::Ack(6)).await;
+
+        for block_id in 7..=10 {
+            let packet = client.recv().await;
+            assert!(matches!(Packet::decode(&packet),
+                             Ok(Packet::Data(id, d))
+                                if id == block_id && d == block(&data, id)));
+        }
+
+        client.send(Packet::Ack(10)).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::Data(11, d)) if d == block(&data, 11)));
+
+        client.send(Packet::Ack(11)).await;
+
+        // Transfer is done
+        assert!(client.try_recv(Duration::from_millis(100)).await.is_none());
+    }));
+}
+
+#[test]
+fn rrq_window_size_limit() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        let (builder, _written_rx) = mem_server(vec![0; 100]);
+        let builder = builder
+            .window_size_limit(2);
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Rrq(req(8))).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::OAck(opts)) if opts.window_size == Some(2)));
+    }));
+}
+
+#[test]
+fn wrq_window() {
+    let ex = Executor::new();
+
+    block_on(ex.run(async {
+        // 6 blocks, the last one is not full
+        let data: Vec<u8> = (0..5 * 512 + 100).map(|x| x as u8).collect();
+        let (builder, written_rx) = mem_server(Vec::new());
+        let (_server, mut client) = start_server(&ex, builder).await;
+
+        client.send(Packet::Wrq(req(4))).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet),
+                         Ok(Packet::OAck(opts)) if opts.window_size == Some(4)));
+
+        // Only the last block of the window is acknowledged
+        for block_id in 1..=4 {
+            client.send(Packet::Data(block_id, block(&data, block_id))).await;
+        }
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(4))));
+
+        // On a gap, the last block received in order is acknowledged
+        client.send(Packet::Data(5, block(&data, 5))).await;
+        client.send(Packet::Data(7, &[])).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(5))));
+
+        client.send(Packet::Data(6, block(&data, 6))).await;
+
+        let packet = client.recv().await;
+        assert!(matches!(Packet::decode(&packet), Ok(Packet::Ack(6))));
+
+        let written = written_rx.recv().await.unwrap();
+        assert_eq!(written, data);
+    }));
+}
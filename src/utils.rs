@@ -1,9 +1,12 @@
+use async_channel::Receiver;
 use async_io::Timer;
 use futures_lite::future;
 use std::future::Future;
 use std::io;
 use std::time::Duration;
 
+use crate::error::{Error, Result};
+
 pub async fn io_timeout<T>(
     dur: Duration,
     f: impl Future<Output = io::Result<T>>,
@@ -14,3 +17,10 @@ pub async fn io_timeout<T>(
     })
     .await
 }
+
+/// Resolves to `Error::Shutdown` when the requests in progress are
+/// cancelled, which is signaled by closing the channel.
+pub(crate) async fn cancelled<T>(cancel: &Receiver<()>) -> Result<T> {
+    let _ = cancel.recv().await;
+    Err(Error::Shutdown)
+}